@@ -0,0 +1,1207 @@
+use dicom::{
+    core::{
+        header::Header,
+        value::{DataSetSequence, Value},
+        DataElement, PrimitiveValue, Tag, VR,
+    },
+    dictionary_std::tags,
+    object::{mem::InMemElement, InMemDicomObject},
+};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    path::Path,
+    sync::LazyLock,
+};
+
+use crate::{header::Attributes, prompt_parser::options::RetainOption};
+
+/// Root of the UIDs, which are derived from UUIDs, see PS3.5 Annex B.2.
+const UUID_ROOT: &str = "2.25";
+
+/// Value of the attributes, which are replaced with a dummy value of a textual VR.
+const DUMMY: &str = "ANONYMIZED";
+
+/// Prefix of the pseudonyms, which replace IDs of the patients.
+pub const PSEUDONYM_PREFIX: &str = "PSEUDO-";
+
+/// What happens to an attribute according to the profile, see PS3.15 Table E.1-1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// `X`: the attribute is removed
+    Remove,
+    /// `Z`: the attribute is kept with an empty value
+    Empty,
+    /// `D`: the value is replaced with a dummy one of the same VR
+    Dummy,
+    /// `U`: each UID is replaced with a new one, which is the same for all of the files of a run
+    Uid,
+}
+
+/// Action of the basic profile on the attribute with the [`tag`], unless the attribute is retained by an option.
+#[derive(Debug)]
+struct Rule {
+    tag: Tag,
+    action: Action,
+    retained_by: Option<RetainOption>,
+}
+
+const fn rule(tag: Tag, action: Action) -> Rule {
+    Rule {
+        tag,
+        action,
+        retained_by: None,
+    }
+}
+
+const fn retained(tag: Tag, action: Action, option: RetainOption) -> Rule {
+    Rule {
+        tag,
+        action,
+        retained_by: Some(option),
+    }
+}
+
+use Action::*;
+use RetainOption::*;
+
+/// Attributes of PS3.15 Table E.1-1 in the alphabetical order of their keywords. Where the table allows
+/// several actions, the most conservative one, which keeps the file valid, is used, so e.g. `X/Z/U*` sequences
+/// are removed. Retired attributes are referred to by their tags. The attributes of the file meta information
+/// are dealt with separately.
+const RULES: &[Rule] = &[
+    rule(tags::ACCESSION_NUMBER, Empty),
+    rule(Tag(0x0018, 0x4000), Remove), // AcquisitionComments
+    rule(tags::ACQUISITION_CONTEXT_DESCRIPTION, Remove),
+    rule(tags::ACQUISITION_CONTEXT_SEQUENCE, Remove),
+    retained(tags::ACQUISITION_DATE, Remove, LongitudinalDates),
+    retained(tags::ACQUISITION_DATE_TIME, Remove, LongitudinalDates),
+    rule(tags::ACQUISITION_DEVICE_PROCESSING_DESCRIPTION, Remove),
+    rule(tags::ACQUISITION_FIELD_OF_VIEW_LABEL, Dummy),
+    rule(tags::ACQUISITION_PROTOCOL_DESCRIPTION, Remove),
+    retained(tags::ACQUISITION_TIME, Remove, LongitudinalDates),
+    retained(tags::ACQUISITION_UID, Uid, Uids),
+    rule(tags::ACTUAL_HUMAN_PERFORMERS_SEQUENCE, Remove),
+    rule(tags::ADDITIONAL_PATIENT_HISTORY, Remove),
+    rule(Tag(0x0040, 0xA353), Remove), // AddressTrial
+    rule(tags::ADMISSION_ID, Remove),
+    retained(tags::ADMITTING_DATE, Remove, LongitudinalDates),
+    rule(tags::ADMITTING_DIAGNOSES_CODE_SEQUENCE, Remove),
+    rule(tags::ADMITTING_DIAGNOSES_DESCRIPTION, Remove),
+    retained(tags::ADMITTING_TIME, Remove, LongitudinalDates),
+    retained(tags::AFFECTED_SOP_INSTANCE_UID, Remove, Uids),
+    rule(tags::ALLERGIES, Remove),
+    rule(tags::ANNOTATION_GROUP_DESCRIPTION, Remove),
+    rule(tags::ANNOTATION_GROUP_LABEL, Dummy),
+    retained(tags::ANNOTATION_GROUP_UID, Uid, Uids),
+    retained(tags::APPROVAL_STATUS_DATE_TIME, Remove, LongitudinalDates),
+    rule(Tag(0x4000, 0x0010), Remove), // Arbitrary
+    rule(tags::ASSERTER_IDENTIFICATION_SEQUENCE, Remove),
+    retained(tags::ASSERTION_DATE_TIME, Dummy, LongitudinalDates),
+    retained(
+        tags::ASSERTION_EXPIRATION_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(tags::ASSERTION_UID, Uid, Uids),
+    rule(tags::AUTHOR_OBSERVER_SEQUENCE, Remove),
+    rule(tags::BARCODE_VALUE, Remove),
+    rule(tags::BEAM_DESCRIPTION, Remove),
+    rule(tags::BRANCH_OF_SERVICE, Remove),
+    rule(tags::BREED_REGISTRATION_NUMBER, Remove),
+    rule(tags::CAMERA_OWNER_NAME, Remove),
+    retained(tags::CASSETTE_ID, Remove, DeviceIdentity),
+    rule(tags::CERTIFICATE_OF_SIGNER, Remove),
+    rule(tags::CERTIFIED_TIMESTAMP, Remove),
+    rule(tags::COMMENTS_ON_THE_PERFORMED_PROCEDURE_STEP, Remove),
+    retained(tags::CONCATENATION_UID, Uid, Uids),
+    retained(tags::CONCEPTUAL_VOLUME_UID, Uid, Uids),
+    rule(tags::CONFIDENTIALITY_CODE, Remove),
+    rule(
+        tags::CONFIDENTIALITY_CONSTRAINT_ON_PATIENT_DATA_DESCRIPTION,
+        Remove,
+    ),
+    rule(tags::CONSENT_FOR_CLINICAL_TRIAL_USE_SEQUENCE, Remove),
+    retained(tags::CONSTITUENT_CONCEPTUAL_VOLUME_UID, Uid, Uids),
+    rule(tags::CONSULTING_PHYSICIAN_IDENTIFICATION_SEQUENCE, Remove),
+    rule(tags::CONSULTING_PHYSICIAN_NAME, Remove),
+    rule(tags::CONTAINER_DESCRIPTION, Remove),
+    rule(tags::CONTAINER_IDENTIFIER, Empty),
+    rule(tags::CONTENT_CREATOR_IDENTIFICATION_CODE_SEQUENCE, Remove),
+    rule(tags::CONTENT_CREATOR_NAME, Empty),
+    retained(tags::CONTENT_DATE, Empty, LongitudinalDates),
+    rule(tags::CONTENT_SEQUENCE, Remove),
+    retained(tags::CONTENT_TIME, Empty, LongitudinalDates),
+    retained(tags::CONTEXT_GROUP_EXTENSION_CREATOR_UID, Uid, Uids),
+    rule(tags::CONTRAST_BOLUS_AGENT, Empty),
+    retained(tags::CONTRAST_BOLUS_START_TIME, Remove, LongitudinalDates),
+    retained(tags::CONTRAST_BOLUS_STOP_TIME, Remove, LongitudinalDates),
+    retained(tags::CONTRIBUTION_DATE_TIME, Remove, LongitudinalDates),
+    rule(tags::CONTRIBUTION_DESCRIPTION, Remove),
+    rule(tags::COUNTRY_OF_RESIDENCE, Remove),
+    retained(tags::CREATOR_VERSION_UID, Uid, Uids),
+    rule(Tag(0x0040, 0xA307), Remove), // CurrentObserverTrial
+    rule(tags::CURRENT_PATIENT_LOCATION, Remove),
+    retained(Tag(0x0008, 0x0025), Remove, LongitudinalDates), // CurveDate
+    retained(Tag(0x0008, 0x0035), Remove, LongitudinalDates), // CurveTime
+    rule(tags::CUSTODIAL_ORGANIZATION_SEQUENCE, Remove),
+    rule(tags::DATA_SET_TRAILING_PADDING, Remove),
+    retained(tags::DATE, Dummy, LongitudinalDates),
+    retained(Tag(0x0040, 0xA110), Remove, LongitudinalDates), // DateOfDocumentOrVerbalTransactionTrial
+    retained(tags::DATE_OF_LAST_CALIBRATION, Remove, LongitudinalDates),
+    retained(
+        tags::DATE_OF_LAST_DETECTOR_CALIBRATION,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(tags::DATE_OF_SECONDARY_CAPTURE, Remove, LongitudinalDates),
+    retained(tags::DATE_TIME, Dummy, LongitudinalDates),
+    retained(tags::DECAY_CORRECTION_DATE_TIME, Remove, LongitudinalDates),
+    rule(tags::DERIVATION_DESCRIPTION, Remove),
+    retained(tags::DETECTOR_ID, Remove, DeviceIdentity),
+    retained(tags::DEVICE_ALTERNATE_IDENTIFIER, Remove, DeviceIdentity),
+    retained(tags::DEVICE_DESCRIPTION, Remove, DeviceIdentity),
+    retained(tags::DEVICE_LABEL, Remove, DeviceIdentity),
+    retained(tags::DEVICE_SERIAL_NUMBER, Remove, DeviceIdentity),
+    retained(tags::DEVICE_SETTING_DESCRIPTION, Remove, DeviceIdentity),
+    retained(tags::DEVICE_UID, Uid, DeviceIdentity),
+    retained(tags::DIGITAL_SIGNATURE_DATE_TIME, Remove, LongitudinalDates),
+    rule(tags::DIGITAL_SIGNATURES_SEQUENCE, Remove),
+    retained(tags::DIGITAL_SIGNATURE_UID, Remove, Uids),
+    retained(tags::DIMENSION_ORGANIZATION_UID, Uid, Uids),
+    retained(Tag(0x0038, 0x0030), Remove, LongitudinalDates), // DischargeDate
+    rule(Tag(0x0038, 0x0044), Remove),                        // DischargeDiagnosisCodeSequence
+    rule(Tag(0x0038, 0x0040), Remove),                        // DischargeDiagnosisDescription
+    retained(Tag(0x0038, 0x0032), Remove, LongitudinalDates), // DischargeTime
+    rule(Tag(0x4008, 0x011A), Remove),                        // DistributionAddress
+    rule(Tag(0x4008, 0x0119), Remove),                        // DistributionName
+    rule(tags::DOCUMENT_TITLE, Remove),
+    rule(tags::DOSE_REFERENCE_DESCRIPTION, Remove),
+    retained(tags::DOSE_REFERENCE_UID, Uid, Uids),
+    retained(tags::DOSIMETRIC_OBJECTIVE_UID, Uid, Uids),
+    rule(tags::ENCRYPTED_ATTRIBUTES_SEQUENCE, Remove),
+    retained(tags::END_ACQUISITION_DATE_TIME, Remove, LongitudinalDates),
+    rule(tags::ENTITY_DESCRIPTION, Remove),
+    rule(tags::ENTITY_LABEL, Dummy),
+    rule(tags::ENTITY_LONG_LABEL, Dummy),
+    rule(tags::ENTITY_NAME, Remove),
+    rule(tags::EQUIPMENT_FRAME_OF_REFERENCE_DESCRIPTION, Remove),
+    retained(tags::ETHNIC_GROUP, Remove, PatientCharacteristics),
+    retained(
+        tags::EXPECTED_COMPLETION_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(tags::FAILED_SOP_INSTANCE_UID_LIST, Uid, Uids),
+    retained(tags::FIDUCIAL_UID, Uid, Uids),
+    rule(tags::FILLER_ORDER_NUMBER_IMAGING_SERVICE_REQUEST, Empty),
+    rule(Tag(0x0040, 0x1007), Remove), // FillerOrderNumberProcedure
+    retained(tags::FIRST_TREATMENT_DATE, Remove, LongitudinalDates),
+    rule(tags::FRACTION_GROUP_DESCRIPTION, Remove),
+    retained(tags::FRAME_ACQUISITION_DATE_TIME, Remove, LongitudinalDates),
+    rule(tags::FRAME_COMMENTS, Remove),
+    retained(tags::FRAME_OF_REFERENCE_UID, Uid, Uids),
+    retained(tags::FRAME_ORIGIN_TIMESTAMP, Remove, LongitudinalDates),
+    retained(tags::FRAME_REFERENCE_DATE_TIME, Remove, LongitudinalDates),
+    retained(tags::GANTRY_ID, Remove, DeviceIdentity),
+    retained(tags::GENERATOR_ID, Remove, DeviceIdentity),
+    rule(tags::GPS_ALTITUDE, Remove),
+    rule(tags::GPS_ALTITUDE_REF, Remove),
+    rule(tags::GPS_AREA_INFORMATION, Remove),
+    rule(tags::GPS_DATE_STAMP, Remove),
+    rule(tags::GPS_DEST_BEARING, Remove),
+    rule(tags::GPS_DEST_BEARING_REF, Remove),
+    rule(tags::GPS_DEST_DISTANCE, Remove),
+    rule(tags::GPS_DEST_DISTANCE_REF, Remove),
+    rule(tags::GPS_DEST_LATITUDE, Remove),
+    rule(tags::GPS_DEST_LATITUDE_REF, Remove),
+    rule(tags::GPS_DEST_LONGITUDE, Remove),
+    rule(tags::GPS_DEST_LONGITUDE_REF, Remove),
+    rule(tags::GPS_DIFFERENTIAL, Remove),
+    rule(tags::GPSDOP, Remove),
+    rule(tags::GPS_IMG_DIRECTION, Remove),
+    rule(tags::GPS_IMG_DIRECTION_REF, Remove),
+    rule(tags::GPS_LATITUDE, Remove),
+    rule(tags::GPS_LATITUDE_REF, Remove),
+    rule(tags::GPS_LONGITUDE, Remove),
+    rule(tags::GPS_LONGITUDE_REF, Remove),
+    rule(tags::GPS_MAP_DATUM, Remove),
+    rule(tags::GPS_MEASURE_MODE, Remove),
+    rule(tags::GPS_PROCESSING_METHOD, Remove),
+    rule(tags::GPS_SATELLITES, Remove),
+    rule(tags::GPS_SPEED, Remove),
+    rule(tags::GPS_SPEED_REF, Remove),
+    rule(tags::GPS_STATUS, Remove),
+    rule(tags::GPS_TIME_STAMP, Remove),
+    rule(tags::GPS_TRACK, Remove),
+    rule(tags::GPS_TRACK_REF, Remove),
+    rule(tags::GPS_VERSION_ID, Remove),
+    rule(tags::GRAPHIC_ANNOTATION_SEQUENCE, Dummy),
+    rule(tags::HL7_INSTANCE_IDENTIFIER, Remove),
+    rule(tags::HUMAN_PERFORMER_NAME, Remove),
+    rule(tags::HUMAN_PERFORMER_ORGANIZATION, Remove),
+    rule(tags::ICON_IMAGE_SEQUENCE, Remove),
+    rule(Tag(0x0008, 0x4000), Remove), // IdentifyingComments
+    rule(tags::IMAGE_COMMENTS, Remove),
+    rule(Tag(0x0028, 0x4000), Remove), // ImagePresentationComments
+    rule(tags::IMAGING_SERVICE_REQUEST_COMMENTS, Remove),
+    rule(Tag(0x4008, 0x0300), Remove), // Impressions
+    retained(tags::INSTANCE_COERCION_DATE_TIME, Remove, LongitudinalDates),
+    retained(tags::INSTANCE_CREATION_DATE, Remove, LongitudinalDates),
+    retained(tags::INSTANCE_CREATION_TIME, Remove, LongitudinalDates),
+    retained(tags::INSTANCE_CREATOR_UID, Uid, Uids),
+    rule(tags::INSTANCE_ORIGIN_STATUS, Remove),
+    retained(tags::INSTITUTION_ADDRESS, Remove, InstitutionIdentity),
+    retained(
+        tags::INSTITUTIONAL_DEPARTMENT_NAME,
+        Remove,
+        InstitutionIdentity,
+    ),
+    retained(
+        tags::INSTITUTIONAL_DEPARTMENT_TYPE_CODE_SEQUENCE,
+        Remove,
+        InstitutionIdentity,
+    ),
+    retained(tags::INSTITUTION_CODE_SEQUENCE, Remove, InstitutionIdentity),
+    retained(tags::INSTITUTION_NAME, Remove, InstitutionIdentity),
+    rule(Tag(0x0010, 0x1050), Remove), // InsurancePlanIdentification
+    retained(
+        tags::INTENDED_FRACTION_START_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(tags::INTENDED_PHASE_END_DATE, Remove, LongitudinalDates),
+    retained(tags::INTENDED_PHASE_START_DATE, Remove, LongitudinalDates),
+    rule(
+        tags::INTENDED_RECIPIENTS_OF_RESULTS_IDENTIFICATION_SEQUENCE,
+        Remove,
+    ),
+    retained(tags::INTERLOCK_DATE_TIME, Dummy, LongitudinalDates),
+    rule(tags::INTERLOCK_DESCRIPTION, Dummy),
+    rule(tags::INTERLOCK_ORIGIN_DESCRIPTION, Dummy),
+    retained(Tag(0x4008, 0x0112), Remove, LongitudinalDates), // InterpretationApprovalDate
+    retained(Tag(0x4008, 0x0113), Remove, LongitudinalDates), // InterpretationApprovalTime
+    rule(Tag(0x4008, 0x0111), Remove),                        // InterpretationApproverSequence
+    rule(Tag(0x4008, 0x010C), Remove),                        // InterpretationAuthor
+    rule(Tag(0x4008, 0x0115), Remove),                        // InterpretationDiagnosisDescription
+    rule(Tag(0x4008, 0x0200), Remove),                        // InterpretationID
+    rule(Tag(0x4008, 0x0202), Remove),                        // InterpretationIDIssuer
+    retained(Tag(0x4008, 0x0100), Remove, LongitudinalDates), // InterpretationRecordedDate
+    retained(Tag(0x4008, 0x0101), Remove, LongitudinalDates), // InterpretationRecordedTime
+    rule(Tag(0x4008, 0x0102), Remove),                        // InterpretationRecorder
+    rule(Tag(0x4008, 0x010B), Remove),                        // InterpretationText
+    rule(Tag(0x4008, 0x010A), Remove),                        // InterpretationTranscriber
+    retained(Tag(0x4008, 0x0108), Remove, LongitudinalDates), // InterpretationTranscriptionDate
+    retained(Tag(0x4008, 0x0109), Remove, LongitudinalDates), // InterpretationTranscriptionTime
+    rule(tags::INTERVENTION_DESCRIPTION, Remove),
+    retained(
+        tags::INTERVENTION_DRUG_START_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(tags::INTERVENTION_DRUG_STOP_TIME, Remove, LongitudinalDates),
+    retained(tags::IRRADIATION_EVENT_UID, Uid, Uids),
+    rule(tags::ISSUER_OF_ACCESSION_NUMBER_SEQUENCE, Remove),
+    rule(Tag(0x0038, 0x0011), Remove), // IssuerOfAdmissionID
+    rule(tags::ISSUER_OF_ADMISSION_ID_SEQUENCE, Remove),
+    rule(tags::ISSUER_OF_PATIENT_ID, Remove),
+    rule(Tag(0x0038, 0x0061), Remove), // IssuerOfServiceEpisodeID
+    rule(tags::ISSUER_OF_SERVICE_EPISODE_ID_SEQUENCE, Remove),
+    rule(tags::LABEL_TEXT, Remove),
+    retained(Tag(0x0028, 0x1214), Uid, Uids), // LargePaletteColorLookupTableUID
+    retained(tags::LAST_MENSTRUAL_DATE, Remove, LongitudinalDates),
+    retained(tags::LENS_SERIAL_NUMBER, Remove, DeviceIdentity),
+    rule(tags::MAC, Remove),
+    rule(tags::MAKER_NOTE, Remove),
+    retained(tags::MAPPING_RESOURCE_UID, Uid, Uids),
+    rule(tags::MEDICAL_ALERTS, Remove),
+    rule(Tag(0x0010, 0x1090), Remove), // MedicalRecordLocator
+    rule(tags::MILITARY_RANK, Remove),
+    rule(tags::MODIFIED_ATTRIBUTES_SEQUENCE, Remove),
+    rule(Tag(0x0020, 0x3406), Remove), // ModifiedImageDescription
+    rule(Tag(0x0020, 0x3401), Remove), // ModifyingDeviceID
+    rule(Tag(0x0020, 0x3404), Remove), // ModifyingDeviceManufacturer
+    rule(tags::NAME_OF_PHYSICIANS_READING_STUDY, Remove),
+    rule(tags::NAMES_OF_INTENDED_RECIPIENTS_OF_RESULTS, Remove),
+    rule(tags::NONCONFORMING_MODIFIED_ATTRIBUTES_SEQUENCE, Remove),
+    retained(tags::OBSERVATION_DATE_TIME, Remove, LongitudinalDates),
+    retained(tags::OBSERVATION_START_DATE_TIME, Remove, LongitudinalDates),
+    rule(Tag(0x0040, 0xA402), Remove), // ObservationSubjectUIDTrial
+    retained(tags::OBSERVATION_UID, Uid, Uids),
+    rule(tags::OCCUPATION, Remove),
+    rule(tags::OPERATOR_IDENTIFICATION_SEQUENCE, Remove),
+    rule(tags::OPERATORS_NAME, Remove),
+    rule(tags::ORDER_CALLBACK_PHONE_NUMBER, Remove),
+    rule(tags::ORDER_CALLBACK_TELECOM_INFORMATION, Remove),
+    rule(tags::ORDER_ENTERED_BY, Remove),
+    rule(tags::ORDER_ENTERER_LOCATION, Remove),
+    rule(tags::ORDER_FILLER_IDENTIFIER_SEQUENCE, Remove),
+    rule(tags::ORDER_PLACER_IDENTIFIER_SEQUENCE, Remove),
+    rule(tags::ORIGINAL_ATTRIBUTES_SEQUENCE, Remove),
+    rule(Tag(0x0010, 0x1000), Remove), // OtherPatientIDs
+    rule(tags::OTHER_PATIENT_I_DS_SEQUENCE, Remove),
+    rule(tags::OTHER_PATIENT_NAMES, Remove),
+    retained(Tag(0x0008, 0x0024), Remove, LongitudinalDates), // OverlayDate
+    retained(Tag(0x0008, 0x0034), Remove, LongitudinalDates), // OverlayTime
+    rule(tags::OWNER_ID, Remove),
+    retained(tags::PALETTE_COLOR_LOOKUP_TABLE_UID, Uid, Uids),
+    rule(tags::PARTICIPANT_SEQUENCE, Remove),
+    retained(tags::PARTICIPATION_DATE_TIME, Remove, LongitudinalDates),
+    rule(tags::PATIENT_ADDRESS, Remove),
+    retained(tags::PATIENT_AGE, Remove, PatientCharacteristics),
+    rule(tags::PATIENT_ALTERNATIVE_CALENDAR, Remove),
+    rule(tags::PATIENT_BIRTH_DATE, Empty),
+    rule(tags::PATIENT_BIRTH_DATE_IN_ALTERNATIVE_CALENDAR, Remove),
+    rule(tags::PATIENT_BIRTH_NAME, Remove),
+    rule(tags::PATIENT_BIRTH_TIME, Remove),
+    rule(tags::PATIENT_COMMENTS, Remove),
+    rule(tags::PATIENT_DEATH_DATE_IN_ALTERNATIVE_CALENDAR, Remove),
+    rule(tags::PATIENT_ID, Empty),
+    rule(tags::PATIENT_INSTITUTION_RESIDENCE, Remove),
+    rule(tags::PATIENT_INSURANCE_PLAN_CODE_SEQUENCE, Remove),
+    rule(tags::PATIENT_MOTHER_BIRTH_NAME, Remove),
+    rule(tags::PATIENT_NAME, Empty),
+    rule(tags::PATIENT_PRIMARY_LANGUAGE_CODE_SEQUENCE, Remove),
+    rule(
+        tags::PATIENT_PRIMARY_LANGUAGE_MODIFIER_CODE_SEQUENCE,
+        Remove,
+    ),
+    rule(tags::PATIENT_RELIGIOUS_PREFERENCE, Remove),
+    rule(tags::PATIENT_SETUP_PHOTO_DESCRIPTION, Remove),
+    retained(Tag(0x300A, 0x0650), Uid, Uids), // PatientSetupUID
+    retained(tags::PATIENT_SEX, Empty, PatientCharacteristics),
+    retained(tags::PATIENT_SEX_NEUTERED, Remove, PatientCharacteristics),
+    retained(tags::PATIENT_SIZE, Remove, PatientCharacteristics),
+    rule(tags::PATIENT_STATE, Remove),
+    rule(tags::PATIENT_TELECOM_INFORMATION, Remove),
+    rule(tags::PATIENT_TELEPHONE_NUMBERS, Remove),
+    rule(tags::PATIENT_TRANSPORT_ARRANGEMENTS, Remove),
+    retained(tags::PATIENT_WEIGHT, Remove, PatientCharacteristics),
+    rule(tags::PERFORMED_LOCATION, Remove),
+    rule(tags::PERFORMED_PROCEDURE_STEP_DESCRIPTION, Remove),
+    retained(
+        tags::PERFORMED_PROCEDURE_STEP_END_DATE,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::PERFORMED_PROCEDURE_STEP_END_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::PERFORMED_PROCEDURE_STEP_END_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    rule(tags::PERFORMED_PROCEDURE_STEP_ID, Remove),
+    retained(
+        tags::PERFORMED_PROCEDURE_STEP_START_DATE,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::PERFORMED_PROCEDURE_STEP_START_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::PERFORMED_PROCEDURE_STEP_START_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    rule(tags::PERFORMED_STATION_AE_TITLE, Remove),
+    rule(
+        tags::PERFORMED_STATION_GEOGRAPHIC_LOCATION_CODE_SEQUENCE,
+        Remove,
+    ),
+    rule(tags::PERFORMED_STATION_NAME, Remove),
+    rule(tags::PERFORMED_STATION_NAME_CODE_SEQUENCE, Remove),
+    rule(tags::PERFORMING_PHYSICIAN_IDENTIFICATION_SEQUENCE, Remove),
+    rule(tags::PERFORMING_PHYSICIAN_NAME, Remove),
+    rule(tags::PERSON_ADDRESS, Remove),
+    rule(tags::PERSON_IDENTIFICATION_CODE_SEQUENCE, Dummy),
+    rule(tags::PERSON_NAME, Dummy),
+    rule(tags::PERSON_TELECOM_INFORMATION, Remove),
+    rule(tags::PERSON_TELEPHONE_NUMBERS, Remove),
+    rule(Tag(0x4008, 0x0114), Remove), // PhysicianApprovingInterpretation
+    rule(tags::PHYSICIANS_OF_RECORD, Remove),
+    rule(tags::PHYSICIANS_OF_RECORD_IDENTIFICATION_SEQUENCE, Remove),
+    rule(
+        tags::PHYSICIANS_READING_STUDY_IDENTIFICATION_SEQUENCE,
+        Remove,
+    ),
+    rule(tags::PLACER_ORDER_NUMBER_IMAGING_SERVICE_REQUEST, Empty),
+    rule(Tag(0x0040, 0x1006), Remove), // PlacerOrderNumberProcedure
+    retained(tags::PLATE_ID, Remove, DeviceIdentity),
+    retained(tags::PREGNANCY_STATUS, Remove, PatientCharacteristics),
+    rule(tags::PRE_MEDICATION, Remove),
+    rule(tags::PRESCRIPTION_DESCRIPTION, Remove),
+    rule(tags::PRESCRIPTION_NOTES, Remove),
+    retained(tags::PRESENTATION_DISPLAY_COLLECTION_UID, Uid, Uids),
+    retained(tags::PRESENTATION_SEQUENCE_COLLECTION_UID, Uid, Uids),
+    rule(tags::PRIVATE_INFORMATION, Remove),
+    retained(tags::PRIVATE_INFORMATION_CREATOR_UID, Uid, Uids),
+    retained(
+        tags::PROCEDURE_STEP_CANCELLATION_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    rule(tags::PROTOCOL_NAME, Remove),
+    retained(
+        tags::RADIOPHARMACEUTICAL_START_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::RADIOPHARMACEUTICAL_START_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::RADIOPHARMACEUTICAL_STOP_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::RADIOPHARMACEUTICAL_STOP_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    rule(tags::REASON_FOR_OMISSION_DESCRIPTION, Remove),
+    rule(Tag(0x0032, 0x1030), Remove), // ReasonForStudy
+    rule(Tag(0x0040, 0x2001), Remove), // ReasonForTheImagingServiceRequest
+    rule(tags::REASON_FOR_THE_REQUESTED_PROCEDURE, Remove),
+    rule(tags::REASON_FOR_VISIT, Remove),
+    rule(tags::REASON_FOR_VISIT_CODE_SEQUENCE, Remove),
+    rule(tags::RECEIVING_APPLICATION_ENTITY_TITLE, Remove),
+    retained(tags::REFERENCED_DATE_TIME, Dummy, LongitudinalDates),
+    rule(tags::REFERENCED_DIGITAL_SIGNATURE_SEQUENCE, Remove),
+    retained(tags::REFERENCED_DOSE_REFERENCE_UID, Uid, Uids),
+    retained(tags::REFERENCED_FRAME_OF_REFERENCE_UID, Uid, Uids),
+    retained(Tag(0x0040, 0x4023), Uid, Uids), // ReferencedGeneralPurposeScheduledProcedureStepTransactionUID
+    retained(tags::REFERENCED_IMAGE_SEQUENCE, Remove, Uids),
+    retained(Tag(0x0040, 0xA172), Uid, Uids), // ReferencedObservationUIDTrial
+    rule(Tag(0x0038, 0x0004), Remove),        // ReferencedPatientAliasSequence
+    rule(tags::REFERENCED_PATIENT_PHOTO_SEQUENCE, Remove),
+    rule(tags::REFERENCED_PATIENT_SEQUENCE, Remove),
+    retained(
+        tags::REFERENCED_PERFORMED_PROCEDURE_STEP_SEQUENCE,
+        Remove,
+        Uids,
+    ),
+    rule(tags::REFERENCED_REQUEST_SEQUENCE, Remove),
+    rule(tags::REFERENCED_SOP_INSTANCE_MAC_SEQUENCE, Remove),
+    retained(tags::REFERENCED_SOP_INSTANCE_UID, Uid, Uids),
+    retained(tags::REFERENCED_SOP_INSTANCE_UID_IN_FILE, Uid, Uids),
+    retained(tags::REFERENCED_STUDY_SEQUENCE, Remove, Uids),
+    rule(tags::REFERRING_PHYSICIAN_ADDRESS, Remove),
+    rule(tags::REFERRING_PHYSICIAN_IDENTIFICATION_SEQUENCE, Remove),
+    rule(tags::REFERRING_PHYSICIAN_NAME, Empty),
+    rule(tags::REFERRING_PHYSICIAN_TELEPHONE_NUMBERS, Remove),
+    rule(tags::REGION_OF_RESIDENCE, Remove),
+    retained(Tag(0x3006, 0x00C2), Uid, Uids), // RelatedFrameOfReferenceUID
+    rule(tags::REQUEST_ATTRIBUTES_SEQUENCE, Remove),
+    rule(tags::REQUESTED_CONTRAST_AGENT, Remove),
+    rule(tags::REQUESTED_PROCEDURE_COMMENTS, Remove),
+    rule(tags::REQUESTED_PROCEDURE_DESCRIPTION, Remove),
+    rule(tags::REQUESTED_PROCEDURE_ID, Remove),
+    rule(tags::REQUESTED_PROCEDURE_LOCATION, Remove),
+    retained(tags::REQUESTED_SOP_INSTANCE_UID, Uid, Uids),
+    rule(tags::REQUESTING_PHYSICIAN, Remove),
+    rule(tags::REQUESTING_SERVICE, Remove),
+    rule(tags::RESPONSIBLE_ORGANIZATION, Remove),
+    rule(tags::RESPONSIBLE_PERSON, Remove),
+    rule(Tag(0x4008, 0x4000), Remove), // ResultsComments
+    rule(Tag(0x4008, 0x0118), Remove), // ResultsDistributionListSequence
+    rule(Tag(0x4008, 0x0042), Remove), // ResultsIDIssuer
+    rule(tags::RETRIEVE_URI, Remove),
+    rule(tags::RETRIEVE_URL, Remove),
+    retained(tags::REVIEW_DATE, Remove, LongitudinalDates),
+    rule(tags::REVIEWER_NAME, Remove),
+    retained(tags::REVIEW_TIME, Remove, LongitudinalDates),
+    rule(tags::ROI_DESCRIPTION, Remove),
+    rule(tags::ROI_GENERATION_DESCRIPTION, Remove),
+    rule(tags::ROI_INTERPRETER, Empty),
+    rule(tags::ROUTE_OF_ADMISSIONS, Remove),
+    rule(tags::RT_IMAGE_DESCRIPTION, Remove),
+    rule(tags::RT_IMAGE_LABEL, Dummy),
+    rule(tags::RT_IMAGE_NAME, Remove),
+    retained(tags::RT_PLAN_DATE, Remove, LongitudinalDates),
+    rule(tags::RT_PLAN_DESCRIPTION, Remove),
+    rule(tags::RT_PLAN_LABEL, Dummy),
+    rule(tags::RT_PLAN_NAME, Remove),
+    retained(tags::RT_PLAN_TIME, Remove, LongitudinalDates),
+    retained(tags::SAFE_POSITION_EXIT_DATE, Remove, LongitudinalDates),
+    retained(tags::SAFE_POSITION_EXIT_TIME, Remove, LongitudinalDates),
+    retained(tags::SAFE_POSITION_RETURN_DATE, Remove, LongitudinalDates),
+    retained(tags::SAFE_POSITION_RETURN_TIME, Remove, LongitudinalDates),
+    retained(Tag(0x0038, 0x001A), Remove, LongitudinalDates), // ScheduledAdmissionDate
+    retained(Tag(0x0038, 0x001B), Remove, LongitudinalDates), // ScheduledAdmissionTime
+    retained(Tag(0x0038, 0x001C), Remove, LongitudinalDates), // ScheduledDischargeDate
+    retained(Tag(0x0038, 0x001D), Remove, LongitudinalDates), // ScheduledDischargeTime
+    rule(tags::SCHEDULED_HUMAN_PERFORMERS_SEQUENCE, Remove),
+    rule(Tag(0x0038, 0x001E), Remove), // ScheduledPatientInstitutionResidence
+    rule(
+        tags::SCHEDULED_PERFORMING_PHYSICIAN_IDENTIFICATION_SEQUENCE,
+        Remove,
+    ),
+    rule(tags::SCHEDULED_PERFORMING_PHYSICIAN_NAME, Remove),
+    rule(tags::SCHEDULED_PROCEDURE_STEP_DESCRIPTION, Remove),
+    retained(
+        tags::SCHEDULED_PROCEDURE_STEP_END_DATE,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::SCHEDULED_PROCEDURE_STEP_END_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::SCHEDULED_PROCEDURE_STEP_EXPIRATION_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    rule(tags::SCHEDULED_PROCEDURE_STEP_ID, Remove),
+    rule(tags::SCHEDULED_PROCEDURE_STEP_LOCATION, Remove),
+    retained(
+        tags::SCHEDULED_PROCEDURE_STEP_MODIFICATION_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::SCHEDULED_PROCEDURE_STEP_START_DATE,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::SCHEDULED_PROCEDURE_STEP_START_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::SCHEDULED_PROCEDURE_STEP_START_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    rule(tags::SCHEDULED_STATION_AE_TITLE, Remove),
+    rule(
+        tags::SCHEDULED_STATION_GEOGRAPHIC_LOCATION_CODE_SEQUENCE,
+        Remove,
+    ),
+    rule(tags::SCHEDULED_STATION_NAME, Remove),
+    rule(tags::SCHEDULED_STATION_NAME_CODE_SEQUENCE, Remove),
+    rule(Tag(0x0032, 0x1020), Remove), // ScheduledStudyLocation
+    rule(Tag(0x0032, 0x1021), Remove), // ScheduledStudyLocationAETitle
+    retained(Tag(0x0032, 0x1000), Remove, LongitudinalDates), // ScheduledStudyStartDate
+    retained(Tag(0x0032, 0x1001), Remove, LongitudinalDates), // ScheduledStudyStartTime
+    retained(Tag(0x0032, 0x1010), Remove, LongitudinalDates), // ScheduledStudyStopDate
+    retained(Tag(0x0032, 0x1011), Remove, LongitudinalDates), // ScheduledStudyStopTime
+    rule(tags::SENDING_APPLICATION_ENTITY_TITLE, Remove),
+    retained(tags::SERIES_DATE, Remove, LongitudinalDates),
+    rule(tags::SERIES_DESCRIPTION, Remove),
+    rule(tags::SERIES_DESCRIPTION_CODE_SEQUENCE, Remove),
+    retained(tags::SERIES_INSTANCE_UID, Uid, Uids),
+    retained(tags::SERIES_TIME, Remove, LongitudinalDates),
+    rule(tags::SERVICE_EPISODE_DESCRIPTION, Remove),
+    rule(tags::SERVICE_EPISODE_ID, Remove),
+    rule(tags::SIGNATURE, Remove),
+    rule(Tag(0x0040, 0x06FA), Remove), // SlideIdentifier
+    retained(tags::SMOKING_STATUS, Remove, PatientCharacteristics),
+    retained(tags::SOP_INSTANCE_UID, Uid, Uids),
+    rule(tags::SOURCE_APPLICATION_ENTITY_TITLE, Remove),
+    retained(tags::SOURCE_END_DATE_TIME, Remove, LongitudinalDates),
+    retained(tags::SOURCE_FRAME_OF_REFERENCE_UID, Uid, Uids),
+    retained(tags::SOURCE_IMAGE_SEQUENCE, Remove, Uids),
+    retained(tags::SOURCE_MANUFACTURER, Remove, DeviceIdentity),
+    retained(tags::SOURCE_SERIAL_NUMBER, Remove, DeviceIdentity),
+    retained(tags::SOURCE_START_DATE_TIME, Remove, LongitudinalDates),
+    retained(
+        tags::SOURCE_STRENGTH_REFERENCE_DATE,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::SOURCE_STRENGTH_REFERENCE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    rule(tags::SPECIAL_NEEDS, Remove),
+    rule(Tag(0x0040, 0x050A), Remove), // SpecimenAccessionNumber
+    rule(tags::SPECIMEN_DETAILED_DESCRIPTION, Remove),
+    rule(tags::SPECIMEN_IDENTIFIER, Remove),
+    rule(tags::SPECIMEN_SHORT_DESCRIPTION, Remove),
+    retained(tags::SPECIMEN_UID, Uid, Uids),
+    retained(tags::START_ACQUISITION_DATE_TIME, Remove, LongitudinalDates),
+    retained(tags::STATION_AE_TITLE, Remove, DeviceIdentity),
+    retained(tags::STATION_NAME, Remove, DeviceIdentity),
+    retained(tags::STORAGE_MEDIA_FILE_SET_UID, Uid, Uids),
+    retained(tags::STRUCTURE_SET_DATE, Remove, LongitudinalDates),
+    rule(tags::STRUCTURE_SET_DESCRIPTION, Remove),
+    rule(tags::STRUCTURE_SET_LABEL, Dummy),
+    rule(tags::STRUCTURE_SET_NAME, Remove),
+    retained(tags::STRUCTURE_SET_TIME, Remove, LongitudinalDates),
+    retained(Tag(0x0032, 0x1040), Remove, LongitudinalDates), // StudyArrivalDate
+    retained(Tag(0x0032, 0x1041), Remove, LongitudinalDates), // StudyArrivalTime
+    rule(Tag(0x0032, 0x4000), Remove),                        // StudyComments
+    retained(Tag(0x0032, 0x1050), Remove, LongitudinalDates), // StudyCompletionDate
+    retained(Tag(0x0032, 0x1051), Remove, LongitudinalDates), // StudyCompletionTime
+    retained(tags::STUDY_DATE, Empty, LongitudinalDates),
+    rule(tags::STUDY_DESCRIPTION, Remove),
+    rule(tags::STUDY_ID, Empty),
+    rule(Tag(0x0032, 0x0012), Remove), // StudyIDIssuer
+    retained(tags::STUDY_INSTANCE_UID, Uid, Uids),
+    retained(Tag(0x0032, 0x0034), Remove, LongitudinalDates), // StudyReadDate
+    retained(Tag(0x0032, 0x0035), Remove, LongitudinalDates), // StudyReadTime
+    retained(tags::STUDY_TIME, Empty, LongitudinalDates),
+    retained(Tag(0x0032, 0x0032), Remove, LongitudinalDates), // StudyVerifiedDate
+    retained(Tag(0x0032, 0x0033), Remove, LongitudinalDates), // StudyVerifiedTime
+    rule(tags::SUBSTANCE_ADMINISTRATION_APPROVAL, Remove),
+    retained(
+        tags::SUBSTANCE_ADMINISTRATION_DATE_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(tags::SYNCHRONIZATION_FRAME_OF_REFERENCE_UID, Uid, Uids),
+    retained(tags::TABLE_FRAME_OF_REFERENCE_UID, Uid, Uids),
+    retained(tags::TARGET_UID, Uid, Uids),
+    rule(Tag(0x0040, 0xA354), Remove), // TelephoneNumberTrial
+    retained(Tag(0x0040, 0xDB0D), Uid, Uids), // TemplateExtensionCreatorUID
+    retained(Tag(0x0040, 0xDB0C), Uid, Uids), // TemplateExtensionOrganizationUID
+    retained(Tag(0x0040, 0xDB07), Remove, LongitudinalDates), // TemplateLocalVersion
+    retained(Tag(0x0040, 0xDB06), Remove, LongitudinalDates), // TemplateVersion
+    rule(Tag(0x4000, 0x4000), Remove), // TextComments
+    rule(tags::TEXT_STRING, Remove),
+    rule(tags::TEXT_VALUE, Remove),
+    retained(tags::TIME, Dummy, LongitudinalDates),
+    retained(tags::TIME_OF_LAST_CALIBRATION, Remove, LongitudinalDates),
+    retained(tags::TIME_OF_SECONDARY_CAPTURE, Remove, LongitudinalDates),
+    retained(tags::TIMEZONE_OFFSET_FROM_UTC, Remove, LongitudinalDates),
+    rule(Tag(0x0088, 0x0910), Remove), // TopicAuthor
+    rule(Tag(0x0088, 0x0912), Remove), // TopicKeywords
+    rule(Tag(0x0088, 0x0906), Remove), // TopicSubject
+    rule(Tag(0x0088, 0x0904), Remove), // TopicTitle
+    rule(tags::TRACKING_ID, Remove),
+    retained(tags::TRACKING_UID, Uid, Uids),
+    retained(tags::TRANSACTION_UID, Uid, Uids),
+    retained(
+        tags::TREATMENT_CONTROL_POINT_DATE,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(
+        tags::TREATMENT_CONTROL_POINT_TIME,
+        Remove,
+        LongitudinalDates,
+    ),
+    retained(tags::TREATMENT_DATE, Remove, LongitudinalDates),
+    retained(tags::TREATMENT_MACHINE_NAME, Remove, DeviceIdentity),
+    retained(tags::TREATMENT_SESSION_UID, Uid, Uids),
+    retained(tags::TREATMENT_TIME, Remove, LongitudinalDates),
+    retained(tags::UDI_SEQUENCE, Remove, DeviceIdentity),
+    retained(tags::UID, Uid, Uids),
+    rule(Tag(0x0040, 0xA358), Remove), // VerbalSourceIdentifierCodeSequenceTrial
+    rule(Tag(0x0040, 0xA352), Remove), // VerbalSourceTrial
+    retained(tags::VERIFICATION_DATE_TIME, Dummy, LongitudinalDates),
+    rule(tags::VERIFYING_OBSERVER_IDENTIFICATION_CODE_SEQUENCE, Empty),
+    rule(tags::VERIFYING_OBSERVER_NAME, Dummy),
+    rule(tags::VERIFYING_OBSERVER_SEQUENCE, Dummy),
+    rule(tags::VERIFYING_ORGANIZATION, Remove),
+    rule(tags::VISIT_COMMENTS, Remove),
+    retained(tags::VOLUME_FRAME_OF_REFERENCE_UID, Uid, Uids),
+];
+
+/// [`RULES`] by their tags.
+static RULES_BY_TAG: LazyLock<HashMap<Tag, &'static Rule>> =
+    LazyLock::new(|| RULES.iter().map(|rule| (rule.tag, rule)).collect());
+
+impl RetainOption {
+    /// Meaning of the option's code in the DICOM Controlled Terminology, see PS3.16 CID 7050.
+    fn meaning(self) -> &'static str {
+        match self {
+            Self::LongitudinalDates => "Retain Longitudinal Temporal Information Full Dates Option",
+            Self::PatientCharacteristics => "Retain Patient Characteristics Option",
+            Self::DeviceIdentity => "Retain Device Identity Option",
+            Self::Uids => "Retain UIDs Option",
+            Self::InstitutionIdentity => "Retain Institution Identity Option",
+        }
+    }
+}
+
+/// De-identification of DICOM files with the actions of PS3.15 Annex E Basic Application Level Confidentiality
+/// Profile, see [`RULES`], along with the [`RetainOption`]s. Private attributes, curves and overlay comments are
+/// always removed, as are the application entity titles and the private information of the file meta information.
+///
+/// UIDs are replaced with `2.25.<decimal>` ones, which are derived from the originals and a secret of the run,
+/// so that references between the files of the run are kept, while the originals can't be recovered.
+/// A [`pseudonymizing`](Profile::pseudonymizing) profile uses a persistent key instead, so the new UIDs are the
+/// same across runs, and replaces `PatientID` and `PatientName` with a pseudonym of the patient.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::anonymization::Profile;
+/// use dicom::dictionary_std::tags;
+///
+/// let profile = Profile::new(Default::default());
+/// let attributes = [
+///     (tags::PATIENT_ID, "98.12.21".to_string()),
+///     (tags::SERIES_DATE, "20200311".to_string()),
+///     (tags::MODALITY, "CT".to_string()),
+///     (tags::STUDY_INSTANCE_UID, "1.2.156.14702.1.1000.16.0".to_string()),
+/// ]
+/// .into();
+///
+/// let anonymized = profile.apply_to_attributes(&attributes);
+/// assert_eq!(anonymized[&tags::PATIENT_ID], "");
+/// assert!(!anonymized.contains_key(&tags::SERIES_DATE));
+/// assert_eq!(anonymized[&tags::MODALITY], "CT");
+/// assert!(anonymized[&tags::STUDY_INSTANCE_UID].starts_with("2.25."));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    retained: BTreeSet<RetainOption>,
+    /// Secret, which the new UIDs are derived with. It's kept along with the journal, so that
+    /// an interrupted run can be resumed with the same UIDs
+    secret: [u8; 32],
+    #[serde(default)]
+    pseudonymized: bool,
+}
+
+impl Profile {
+    /// Creates a profile with a new random secret, which retains the attributes of the [`retained`] options.
+    pub fn new(retained: BTreeSet<RetainOption>) -> Self {
+        let mut secret = [0; 32];
+        getrandom::fill(&mut secret).expect("Failed getting random bytes");
+
+        Self {
+            retained,
+            secret,
+            pseudonymized: false,
+        }
+    }
+
+    /// Creates a profile, which derives pseudonyms of the patients and the new UIDs with the [`key`],
+    /// and retains the attributes of the [`retained`] options.
+    pub fn pseudonymizing(retained: BTreeSet<RetainOption>, key: [u8; 32]) -> Self {
+        Self {
+            retained,
+            secret: key,
+            pseudonymized: true,
+        }
+    }
+
+    /// Reads the DICOM file at [`source`], de-identifies it and writes it to the [`destination`].
+    pub fn apply(&self, source: &Path, destination: &Path) -> io::Result<()> {
+        let mut obj = dicom::object::open_file(source).map_err(io::Error::other)?;
+        let pseudonym = self.pseudonym_of(
+            obj.get(tags::PATIENT_ID)
+                .and_then(|element| element.to_str().ok())
+                .as_deref(),
+        );
+
+        let dataset = std::mem::replace(&mut *obj, InMemDicomObject::new_empty());
+        *obj = self.apply_to_dataset(dataset);
+        if let Some(pseudonym) = pseudonym {
+            obj.put(DataElement::new(
+                tags::PATIENT_ID,
+                VR::LO,
+                PrimitiveValue::from(pseudonym.as_str()),
+            ));
+            obj.put(DataElement::new(
+                tags::PATIENT_NAME,
+                VR::PN,
+                PrimitiveValue::from(pseudonym),
+            ));
+        }
+        self.mark(&mut obj);
+
+        // The file meta information has to refer to the new SOP instance and mustn't identify the sending stations
+        let meta = obj.meta_mut();
+        meta.source_application_entity_title = None;
+        meta.sending_application_entity_title = None;
+        meta.receiving_application_entity_title = None;
+        meta.private_information_creator_uid = None;
+        meta.private_information = None;
+        if let Some(uid) = obj
+            .get(tags::SOP_INSTANCE_UID)
+            .and_then(|element| element.to_str().ok())
+        {
+            obj.meta_mut().media_storage_sop_instance_uid = uid.trim_end_matches('\0').to_string();
+        }
+        obj.meta_mut().update_information_group_length();
+
+        // An existing file is never replaced
+        let file = crate::transfer::create_new(destination)?;
+        obj.write_all(file).map_err(io::Error::other)
+    }
+
+    /// Textual [`attributes`], which have been read from the header of a file, as they are after the de-identification.
+    /// Removed attributes are left out.
+    pub fn apply_to_attributes(&self, attributes: &Attributes) -> Attributes {
+        let pseudonym = self.pseudonym_of(attributes.get(&tags::PATIENT_ID).map(String::as_str));
+
+        let mut anonymized: Attributes = attributes
+            .iter()
+            .filter(|(&tag, _)| !is_always_removed(tag))
+            .filter_map(|(&tag, value)| {
+                let value = match self.action(tag) {
+                    None => value.clone(),
+                    Some(Remove) => return None,
+                    Some(Empty) => String::new(),
+                    Some(Dummy) => DUMMY.to_string(),
+                    Some(Uid) => value
+                        .split('\\')
+                        .map(|uid| self.uid(uid))
+                        .collect::<Vec<_>>()
+                        .join("\\"),
+                };
+                Some((tag, value))
+            })
+            .collect();
+        if let Some(pseudonym) = pseudonym {
+            for tag in [tags::PATIENT_ID, tags::PATIENT_NAME] {
+                anonymized.insert(tag, pseudonym.clone());
+            }
+        }
+        anonymized
+    }
+
+    /// New UID, which replaces the original [`uid`] within the run, or across runs of a pseudonymizing profile.
+    pub fn uid(&self, uid: &str) -> String {
+        let digest = self.keyed_hash(b"uid", uid.trim().trim_end_matches('\0'));
+
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        format!("{UUID_ROOT}.{}", u128::from_be_bytes(bytes))
+    }
+
+    /// Pseudonym, which replaces the patient's [`id`].
+    pub fn pseudonym(&self, id: &str) -> String {
+        let digest = self.keyed_hash(b"patient", id.trim().trim_end_matches('\0'));
+
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        format!("{PSEUDONYM_PREFIX}{:016X}", u64::from_be_bytes(bytes))
+    }
+
+    /// Pseudonym of the patient's [`id`], when the profile is pseudonymizing and the ID isn't empty.
+    fn pseudonym_of(&self, id: Option<&str>) -> Option<String> {
+        id.map(|id| id.trim().trim_end_matches('\0'))
+            .filter(|id| self.pseudonymized && !id.is_empty())
+            .map(|id| self.pseudonym(id))
+    }
+
+    /// HMAC-SHA256 of the [`value`] with the secret, which is separated by the [`domain`] of the value,
+    /// so that a pseudonym and a UID of the same value aren't related.
+    fn keyed_hash(&self, domain: &[u8], value: &str) -> [u8; 32] {
+        // HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        mac.update(domain);
+        mac.update(b"\0");
+        mac.update(value.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Action of the profile on the attribute with the [`tag`], if it isn't kept as is.
+    fn action(&self, tag: Tag) -> Option<Action> {
+        let rule = RULES_BY_TAG.get(&tag)?;
+        match rule.retained_by {
+            Some(option) if self.retained.contains(&option) => None,
+            _ => Some(rule.action),
+        }
+    }
+
+    fn apply_to_dataset(&self, dataset: InMemDicomObject) -> InMemDicomObject {
+        InMemDicomObject::from_element_iter(
+            dataset
+                .into_iter()
+                .filter_map(|element| self.apply_to_element(element)),
+        )
+    }
+
+    fn apply_to_element(&self, element: InMemElement) -> Option<InMemElement> {
+        let (tag, vr) = (element.tag(), element.vr());
+        if is_always_removed(tag) {
+            return None;
+        }
+
+        match self.action(tag) {
+            Some(Remove) => None,
+            // Items of a sequence can't be replaced with dummy ones, which would be valid, so they're left out
+            Some(Empty | Dummy) if vr == VR::SQ => Some(DataElement::new(
+                tag,
+                vr,
+                DataSetSequence::from(Vec::<InMemDicomObject>::new()),
+            )),
+            Some(Empty) => Some(DataElement::new(tag, vr, PrimitiveValue::Empty)),
+            Some(Dummy) => Some(DataElement::new(tag, vr, dummy(vr))),
+            Some(Uid) => {
+                let uids = element
+                    .to_multi_str()
+                    .map(|uids| uids.iter().map(|uid| self.uid(uid)).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                Some(DataElement::new(tag, vr, PrimitiveValue::Strs(uids.into())))
+            }
+            // Kept sequences may contain attributes, which aren't kept, e.g. references to the other instances
+            None => match element.into_value() {
+                Value::Sequence(sequence) => {
+                    let items: Vec<_> = sequence
+                        .into_items()
+                        .into_iter()
+                        .map(|item| self.apply_to_dataset(item))
+                        .collect();
+                    Some(DataElement::new(tag, vr, DataSetSequence::from(items)))
+                }
+                value => Some(DataElement::new(tag, vr, value)),
+            },
+        }
+    }
+
+    /// Records, that the patient's identity has been removed, and the way it has been done. The conformance
+    /// to the profile isn't claimed with its code in `DeidentificationMethodCodeSequence`, as long as
+    /// [`RULES`] haven't been verified against the whole Table E.1-1 of the current edition of the standard.
+    fn mark(&self, dataset: &mut InMemDicomObject) {
+        let method = if self.pseudonymized {
+            "Based on DICOM PS3.15 Basic Profile, pseudonymized"
+        } else {
+            "Based on DICOM PS3.15 Basic Profile"
+        };
+        let methods: Vec<_> = [method]
+            .into_iter()
+            .chain(self.retained.iter().map(|option| option.meaning()))
+            .map(String::from)
+            .collect();
+        let temporal = if self.retained.contains(&LongitudinalDates) {
+            "UNMODIFIED"
+        } else {
+            "REMOVED"
+        };
+
+        dataset.put(DataElement::new(
+            tags::PATIENT_IDENTITY_REMOVED,
+            VR::CS,
+            PrimitiveValue::from("YES"),
+        ));
+        dataset.put(DataElement::new(
+            tags::DEIDENTIFICATION_METHOD,
+            VR::LO,
+            PrimitiveValue::Strs(methods.into()),
+        ));
+        dataset.put(DataElement::new(
+            tags::LONGITUDINAL_TEMPORAL_INFORMATION_MODIFIED,
+            VR::CS,
+            PrimitiveValue::from(temporal),
+        ));
+    }
+}
+
+/// Checks whether the attribute with the [`tag`] is removed regardless of the options: private attributes,
+/// curves `(50xx,xxxx)`, overlay data `(60xx,3000)` and overlay comments `(60xx,4000)`.
+fn is_always_removed(Tag(group, element): Tag) -> bool {
+    group % 2 == 1
+        || group & 0xFF00 == 0x5000
+        || (group & 0xFF00 == 0x6000 && matches!(element, 0x3000 | 0x4000))
+}
+
+/// Dummy value of the [`vr`], which replaces the original one.
+fn dummy(vr: VR) -> PrimitiveValue {
+    match vr {
+        VR::DA => PrimitiveValue::from("19000101"),
+        VR::TM => PrimitiveValue::from("000000"),
+        VR::DT => PrimitiveValue::from("19000101000000"),
+        VR::AE | VR::CS | VR::LO | VR::LT | VR::PN | VR::SH | VR::ST | VR::UC | VR::UT => {
+            PrimitiveValue::from(DUMMY)
+        }
+        _ => PrimitiveValue::Empty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_is_applied_to_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Path::new("test_small_dir/56364403.dcm");
+        let destination = dir.path().join("1.dcm");
+        let original = dicom::object::open_file(source).unwrap();
+        let value = |obj: &InMemDicomObject, tag| {
+            obj.get(tag)
+                .map(|element| element.to_str().unwrap().trim_end_matches('\0').to_string())
+        };
+
+        let profile = Profile::new([DeviceIdentity].into());
+        profile.apply(source, &destination).unwrap();
+        let anonymized = dicom::object::open_file(&destination).unwrap();
+
+        assert_eq!(value(&anonymized, tags::PATIENT_ID).as_deref(), Some(""));
+        assert_eq!(
+            value(&anonymized, tags::PATIENT_IDENTITY_REMOVED).as_deref(),
+            Some("YES")
+        );
+        assert_eq!(value(&anonymized, tags::STUDY_DESCRIPTION), None);
+        assert!(anonymized
+            .iter()
+            .all(|element| element.tag().group() % 2 == 0));
+        // Retained and unrelated attributes are kept
+        assert_eq!(
+            value(&anonymized, tags::STATION_NAME),
+            value(&original, tags::STATION_NAME)
+        );
+        assert_eq!(value(&anonymized, tags::MODALITY).as_deref(), Some("CT"));
+
+        let sop_uid = value(&anonymized, tags::SOP_INSTANCE_UID).unwrap();
+        let original_sop_uid = value(&original, tags::SOP_INSTANCE_UID).unwrap();
+        assert_eq!(sop_uid, profile.uid(&original_sop_uid));
+        assert_eq!(anonymized.meta().media_storage_sop_instance_uid(), sop_uid);
+        assert_eq!(
+            anonymized.get(tags::PIXEL_DATA).map(|element| element
+                .value()
+                .to_bytes()
+                .unwrap()
+                .len()),
+            original
+                .get(tags::PIXEL_DATA)
+                .map(|element| element.value().to_bytes().unwrap().len())
+        );
+    }
+
+    #[test]
+    fn test_identifying_attributes_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, destination) = (dir.path().join("0.dcm"), dir.path().join("1.dcm"));
+        let mut obj = dicom::object::open_file("test_small_dir/56364403.dcm").unwrap();
+        let removed = [
+            (tags::SCHEDULED_PERFORMING_PHYSICIAN_NAME, VR::PN),
+            (tags::SCHEDULED_STATION_NAME, VR::SH),
+            (tags::SCHEDULED_STATION_AE_TITLE, VR::AE),
+            (tags::PERFORMED_STATION_NAME, VR::SH),
+            (tags::PERFORMED_STATION_AE_TITLE, VR::AE),
+            (tags::VERIFYING_ORGANIZATION, VR::LO),
+            (tags::TIMEZONE_OFFSET_FROM_UTC, VR::SH),
+            (tags::ACQUISITION_DEVICE_PROCESSING_DESCRIPTION, VR::LO),
+        ];
+        let sequences = [
+            tags::ORIGINAL_ATTRIBUTES_SEQUENCE,
+            tags::DIGITAL_SIGNATURES_SEQUENCE,
+            tags::REFERENCED_PATIENT_PHOTO_SEQUENCE,
+            tags::AUTHOR_OBSERVER_SEQUENCE,
+            tags::PARTICIPANT_SEQUENCE,
+            tags::CUSTODIAL_ORGANIZATION_SEQUENCE,
+        ];
+        for (tag, vr) in removed {
+            obj.put(DataElement::new(tag, vr, PrimitiveValue::from("Doe^John")));
+        }
+        obj.put(DataElement::new(
+            Tag(0x0032, 0x4000), // StudyComments
+            VR::LT,
+            PrimitiveValue::from("Doe^John"),
+        ));
+        let item = InMemDicomObject::from_element_iter([DataElement::new(
+            tags::PATIENT_NAME,
+            VR::PN,
+            PrimitiveValue::from("Doe^John"),
+        )]);
+        for tag in sequences {
+            obj.put(DataElement::new(
+                tag,
+                VR::SQ,
+                DataSetSequence::from(vec![item.clone()]),
+            ));
+        }
+        obj.put(DataElement::new(
+            tags::TARGET_UID,
+            VR::UI,
+            PrimitiveValue::from("1.2.3.4"),
+        ));
+        obj.put(DataElement::new(
+            tags::VERIFYING_OBSERVER_SEQUENCE,
+            VR::SQ,
+            DataSetSequence::from(vec![item]),
+        ));
+        obj.write_to_file(&source).unwrap();
+
+        let profile = Profile::new(BTreeSet::new());
+        profile.apply(&source, &destination).unwrap();
+        let anonymized = dicom::object::open_file(&destination).unwrap();
+
+        for tag in removed
+            .map(|(tag, _)| tag)
+            .into_iter()
+            .chain(sequences)
+            .chain([Tag(0x0032, 0x4000)])
+        {
+            assert!(anonymized.get(tag).is_none(), "{tag} is kept");
+        }
+        assert_eq!(
+            anonymized.get(tags::TARGET_UID).unwrap().to_str().unwrap(),
+            profile.uid("1.2.3.4")
+        );
+        let verifying_observers = anonymized.get(tags::VERIFYING_OBSERVER_SEQUENCE).unwrap();
+        assert_eq!(verifying_observers.items().map(<[_]>::len), Some(0));
+        assert!(anonymized
+            .get(tags::DEIDENTIFICATION_METHOD_CODE_SEQUENCE)
+            .is_none());
+    }
+
+    #[test]
+    fn test_uids_are_consistent_within_a_run() {
+        let profile = Profile::new(BTreeSet::new());
+        let uid = profile.uid("1.2.156.14702.1.1000.16.0");
+
+        assert_eq!(profile.uid("1.2.156.14702.1.1000.16.0\0"), uid);
+        assert_ne!(profile.uid("1.2.156.14702.1.1000.16.1"), uid);
+        assert_ne!(
+            Profile::new(BTreeSet::new()).uid("1.2.156.14702.1.1000.16.0"),
+            uid
+        );
+        assert!(uid.len() <= 64);
+        assert!(uid[UUID_ROOT.len() + 1..]
+            .chars()
+            .all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn test_pseudonyms_are_consistent_across_runs() {
+        let key = [7; 32];
+        let profile = Profile::pseudonymizing(BTreeSet::new(), key);
+        let attributes: Attributes = [
+            (tags::PATIENT_ID, "98.12.21".to_string()),
+            (tags::PATIENT_NAME, "Doe^John".to_string()),
+            (
+                tags::STUDY_INSTANCE_UID,
+                "1.2.156.14702.1.1000.16.0".to_string(),
+            ),
+        ]
+        .into();
+
+        let pseudonymized = profile.apply_to_attributes(&attributes);
+        let pseudonym = &pseudonymized[&tags::PATIENT_ID];
+        assert!(pseudonym.starts_with(PSEUDONYM_PREFIX));
+        assert_eq!(&pseudonymized[&tags::PATIENT_NAME], pseudonym);
+        assert_eq!(
+            Profile::pseudonymizing(BTreeSet::new(), key).apply_to_attributes(&attributes),
+            pseudonymized
+        );
+        assert_ne!(
+            Profile::pseudonymizing(BTreeSet::new(), [8; 32]).pseudonym("98.12.21"),
+            *pseudonym
+        );
+        // Anonymizing profiles don't make pseudonyms
+        assert_eq!(
+            Profile::new(BTreeSet::new()).apply_to_attributes(&attributes)[&tags::PATIENT_ID],
+            ""
+        );
+        // Files without an ID, which `anonymize` leaves unidentified, aren't given a pseudonym
+        let attributes = [(tags::PATIENT_ID, String::new())].into();
+        assert_eq!(
+            profile.apply_to_attributes(&attributes)[&tags::PATIENT_ID],
+            ""
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use dicom::{core::Tag, dictionary_std::tags};
+use serde::{Serialize, Serializer};
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{header::Attributes, utils::SortedPaths};
+
+/// Tags, which place a DICOM file into the `Study -> Series -> Instance` hierarchy of a patient.
+pub const HIERARCHY_TAGS: [Tag; 7] = [
+    tags::STUDY_INSTANCE_UID,
+    tags::STUDY_DATE,
+    tags::STUDY_DESCRIPTION,
+    tags::SERIES_INSTANCE_UID,
+    tags::SERIES_NUMBER,
+    tags::MODALITY,
+    tags::SERIES_DESCRIPTION,
+];
+
+/// Studies of a single patient, keyed by their `StudyInstanceUID`.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Patient {
+    #[serde(serialize_with = "serialize_values")]
+    pub studies: BTreeMap<String, Study>,
+}
+
+/// A single study of a patient with its series, keyed by their `SeriesInstanceUID`.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Study {
+    pub uid: String,
+    pub date: String,
+    pub description: String,
+    #[serde(serialize_with = "serialize_values")]
+    pub series: BTreeMap<String, Series>,
+}
+
+/// A single series of a study with the paths of its instances.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Series {
+    pub uid: String,
+    pub number: String,
+    pub modality: String,
+    pub description: String,
+    pub instances: SortedPaths,
+}
+
+/// Attributes of a single DICOM file, which are needed to place it into the [`Patient`]'s hierarchy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instance {
+    pub path: PathBuf,
+    pub study_uid: String,
+    pub study_date: String,
+    pub study_description: String,
+    pub series_uid: String,
+    pub series_number: String,
+    pub modality: String,
+    pub series_description: String,
+}
+
+impl Instance {
+    /// Picks hierarchy attributes out of the file's [`attributes`]. Missing attributes are left empty.
+    pub fn from_attributes(path: PathBuf, attributes: &Attributes) -> Self {
+        let value = |tag| attributes.get(&tag).cloned().unwrap_or_default();
+
+        Self {
+            path,
+            study_uid: value(tags::STUDY_INSTANCE_UID),
+            study_date: value(tags::STUDY_DATE),
+            study_description: value(tags::STUDY_DESCRIPTION),
+            series_uid: value(tags::SERIES_INSTANCE_UID),
+            series_number: value(tags::SERIES_NUMBER),
+            modality: value(tags::MODALITY),
+            series_description: value(tags::SERIES_DESCRIPTION),
+        }
+    }
+}
+
+impl Patient {
+    /// Builds the hierarchy of a patient out of its [`instances`].
+    pub fn new<I: IntoIterator<Item = Instance>>(instances: I) -> Self {
+        let mut patient = Self::default();
+        for instance in instances {
+            patient.insert(instance);
+        }
+        patient
+    }
+
+    /// Places the [`instance`] into its study and series, creating them when needed.
+    pub fn insert(&mut self, instance: Instance) {
+        let study = self
+            .studies
+            .entry(instance.study_uid.clone())
+            .or_insert_with(|| Study {
+                uid: instance.study_uid,
+                date: instance.study_date,
+                description: instance.study_description,
+                series: BTreeMap::new(),
+            });
+
+        let series = study
+            .series
+            .entry(instance.series_uid.clone())
+            .or_insert_with(|| Series {
+                uid: instance.series_uid,
+                number: instance.series_number,
+                modality: instance.modality,
+                description: instance.series_description,
+                instances: SortedPaths::default(),
+            });
+
+        series.instances.insert(instance.path);
+    }
+
+    /// Amount of DICOM files of the patient across all of the studies.
+    pub fn instance_count(&self) -> usize {
+        self.studies.values().map(Study::instance_count).sum()
+    }
+
+    /// All paths of the patient's DICOM files, sorted in the topological order.
+    pub fn paths(&self) -> SortedPaths {
+        let paths: Vec<PathBuf> = self
+            .studies
+            .values()
+            .flat_map(|study| study.series.values())
+            .flat_map(|series| series.instances.iter().cloned())
+            .collect();
+
+        SortedPaths::new(paths)
+    }
+}
+
+impl Study {
+    /// Amount of DICOM files in the study across all of the series.
+    pub fn instance_count(&self) -> usize {
+        self.series
+            .values()
+            .map(|series| series.instances.len())
+            .sum()
+    }
+}
+
+/// Serializes a map as a sequence of its values, since they already contain their keys.
+fn serialize_values<S, V>(map: &BTreeMap<String, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    V: Serialize,
+{
+    serializer.collect_seq(map.values())
+}
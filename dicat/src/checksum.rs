@@ -0,0 +1,254 @@
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::output;
+
+/// File in each patient's directory, which lists SHA-256 checksums of its files in the `sha256sum` format.
+pub const MANIFEST_FILE: &str = "checksums.sha256";
+
+/// SHA-256 checksum of a file's content.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::checksum::Checksum;
+///
+/// let checksum = Checksum::of_bytes(b"abc");
+/// let hex = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+/// assert_eq!(checksum.to_string(), hex);
+/// assert_eq!(hex.parse(), Ok(checksum));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checksum([u8; 32]);
+
+impl Checksum {
+    pub fn of_bytes(bytes: &[u8]) -> Self {
+        Self(Sha256::digest(bytes).into())
+    }
+
+    /// Reads the file at [`path`] and computes the checksum of its content.
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        Self::copy(&mut file, &mut io::sink())
+    }
+
+    /// Copies everything from the [`reader`] into the [`writer`] and computes the checksum of the copied content
+    /// on the way, so that it's read only once.
+    pub fn copy(reader: &mut impl Read, writer: &mut impl Write) -> io::Result<Self> {
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(Self(hasher.finalize().into())),
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            hasher.update(&buffer[..read]);
+            writer.write_all(&buffer[..read])?;
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(());
+        }
+
+        let mut bytes = [0; 32];
+        for (byte, hex) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+            // The string is ASCII, so each chunk is a valid `str`
+            let hex = std::str::from_utf8(hex).unwrap();
+            *byte = u8::from_str_radix(hex, 16).map_err(|_| ())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// Result of checking files against the manifests of a directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// Amount of the files, which match their checksums
+    pub verified: usize,
+    /// Files, which content doesn't match their checksums
+    pub mismatched: Vec<PathBuf>,
+    /// Files, which are listed in the manifests, but can't be read
+    pub missing: Vec<PathBuf>,
+}
+
+impl Verification {
+    pub fn has_failed(&self) -> bool {
+        !self.mismatched.is_empty() || !self.missing.is_empty()
+    }
+}
+
+/// Adds [`checksums`] of the files, which have been restructured into the [`root`], to the manifests of their
+/// patient's directories, i.e. the top-level directories of the [`root`]. Files directly in the [`root`] are listed
+/// in its own manifest. Existing manifests are kept, and only checksums of the same files are replaced in them.
+pub fn write_manifests(root: &Path, checksums: &[(PathBuf, Checksum)]) -> io::Result<()> {
+    let root = std::path::absolute(root)?;
+    let mut manifests: BTreeMap<PathBuf, BTreeMap<String, Checksum>> = BTreeMap::new();
+
+    for (file, checksum) in checksums {
+        let file = std::path::absolute(file)?;
+        let directory = output::patient_directory(&root, &file);
+        // Each file is under its patient's directory by construction
+        let name = file.strip_prefix(&directory).unwrap();
+        let name = manifest_name(name);
+
+        manifests
+            .entry(directory)
+            .or_default()
+            .insert(name, *checksum);
+    }
+
+    for (directory, checksums) in manifests {
+        let manifest = directory.join(MANIFEST_FILE);
+        let mut entries = if manifest.exists() {
+            read_manifest(&manifest)?.into_iter().collect()
+        } else {
+            BTreeMap::new()
+        };
+        entries.extend(checksums);
+
+        let content: String = entries
+            .iter()
+            .map(|(name, checksum)| format!("{checksum}  {name}\n"))
+            .collect();
+        std::fs::write(manifest, content)?;
+    }
+
+    Ok(())
+}
+
+/// Checks all of the files, which are listed in the manifests under the [`directory`], against their checksums.
+/// Fails, when the manifests can't be read.
+pub fn verify_directory(directory: &Path) -> io::Result<Verification> {
+    let mut verification = Verification::default();
+
+    let manifests = walkdir::WalkDir::new(directory)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && entry.file_name() == MANIFEST_FILE);
+    for manifest in manifests {
+        // Manifests are always inside of the walked directory
+        let manifest_directory = manifest.path().parent().unwrap();
+
+        for (name, expected) in read_manifest(manifest.path())? {
+            let file = manifest_directory.join(name);
+            match Checksum::of_file(&file) {
+                Ok(checksum) if checksum == expected => verification.verified += 1,
+                Ok(_) => verification.mismatched.push(file),
+                Err(_) => verification.missing.push(file),
+            }
+        }
+    }
+
+    Ok(verification)
+}
+
+/// Reads names of the files and their checksums out of the manifest at [`path`].
+fn read_manifest(path: &Path) -> io::Result<Vec<(String, Checksum)>> {
+    let invalid_data = || io::Error::new(io::ErrorKind::InvalidData, path.display().to_string());
+
+    std::fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (checksum, name) = line.split_once(' ').ok_or_else(invalid_data)?;
+            let checksum = checksum.parse().map_err(|_| invalid_data())?;
+            // `sha256sum` marks the files, which have been read in the binary mode, with `*`
+            let name = name.strip_prefix([' ', '*']).ok_or_else(invalid_data)?;
+            Ok((name.to_string(), checksum))
+        })
+        .collect()
+}
+
+/// Relative path of a file in the manifest, which is separated by `/` on all platforms.
+fn manifest_name(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifests_are_verified() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("A/2023"))?;
+        std::fs::create_dir_all(root.join("B"))?;
+
+        let files = ["A/1.dcm", "A/2023/2.dcm", "B/3.dcm", "4.dcm"];
+        let mut checksums = Vec::new();
+        for file in files {
+            std::fs::write(root.join(file), file)?;
+            checksums.push((root.join(file), Checksum::of_bytes(file.as_bytes())));
+        }
+        write_manifests(root, &checksums[..3])?;
+        // Manifests are extended by the subsequent runs
+        write_manifests(root, &checksums[3..])?;
+
+        let manifest = std::fs::read_to_string(root.join("A").join(MANIFEST_FILE))?;
+        assert_eq!(
+            manifest,
+            format!(
+                "{}  1.dcm\n{}  2023/2.dcm\n",
+                checksums[0].1, checksums[1].1
+            )
+        );
+        assert!(root.join("B").join(MANIFEST_FILE).exists());
+        assert!(root.join(MANIFEST_FILE).exists());
+
+        let verification = verify_directory(root)?;
+        assert_eq!(verification.verified, 4);
+        assert!(!verification.has_failed());
+
+        std::fs::write(root.join("A/2023/2.dcm"), "changed")?;
+        std::fs::remove_file(root.join("B/3.dcm"))?;
+        let verification = verify_directory(root)?;
+        assert_eq!(
+            verification,
+            Verification {
+                verified: 2,
+                mismatched: vec![root.join("A").join("2023/2.dcm")],
+                missing: vec![root.join("B").join("3.dcm")],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_of_file() -> io::Result<()> {
+        let content = std::fs::read("test_small_dir/1-010.dcm")?;
+        let checksum = Checksum::of_file(Path::new("test_small_dir/1-010.dcm"))?;
+        assert_eq!(checksum, Checksum::of_bytes(&content));
+
+        let mut copied = Vec::new();
+        let checksum = Checksum::copy(&mut content.as_slice(), &mut copied)?;
+        assert_eq!(copied, content);
+        assert_eq!(checksum, Checksum::of_bytes(&content));
+        assert!("not a checksum".parse::<Checksum>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,376 @@
+use dicom::core::Tag;
+use regex::{Regex, RegexBuilder};
+use std::{cmp::Ordering, str::FromStr};
+
+use crate::header::{Attributes, NamedTag};
+
+/// Boolean expression over DICOM attributes, which decides whether a file is selected.
+///
+/// Comparisons have the form `Tag op Value`, where `Tag` is a dictionary keyword or `(gggg,eeee)`,
+/// `Value` is either a bare word or a `"quoted string"` and `op` is one of:
+/// * `==`, `!=`, `<`, `<=`, `>`, `>=`, which compare values as numbers, when both of them are numbers,
+///   and as strings otherwise
+/// * `~`, which checks whether the value matches a case-insensitive regular expression
+///
+/// Comparisons can be combined with `&&`, `||`, `!` and parentheses. Absent attributes are treated as empty strings.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::filter::Filter;
+/// use dicom::dictionary_std::tags;
+///
+/// let filter: Filter = r#"Modality == "CT" && StudyDate >= 20230101"#.parse().unwrap();
+/// let attributes = [
+///     (tags::MODALITY, "CT".to_string()),
+///     (tags::STUDY_DATE, "20230512".to_string()),
+/// ]
+/// .into();
+/// assert!(filter.matches(&attributes));
+/// ```
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Comparison(Tag, Operator, Operand),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Matches,
+}
+
+/// Right-hand side of a comparison. Regular expressions are compiled once, while parsing.
+#[derive(Debug, Clone)]
+pub enum Operand {
+    Value(String),
+    Regex(Regex),
+}
+
+impl Filter {
+    /// Checks whether the file with the given [`attributes`] satisfies the expression.
+    pub fn matches(&self, attributes: &Attributes) -> bool {
+        match self {
+            Self::Comparison(tag, operator, operand) => {
+                let value = attributes.get(tag).map_or("", String::as_str);
+                match (operator, operand) {
+                    (Operator::Matches, Operand::Regex(regex)) => regex.is_match(value),
+                    (operator, Operand::Value(expected)) => {
+                        let ordering = compare(value, expected);
+                        match operator {
+                            Operator::Eq => ordering == Ordering::Equal,
+                            Operator::Ne => ordering != Ordering::Equal,
+                            Operator::Lt => ordering == Ordering::Less,
+                            Operator::Le => ordering != Ordering::Greater,
+                            Operator::Gt => ordering == Ordering::Greater,
+                            Operator::Ge => ordering != Ordering::Less,
+                            Operator::Matches => false,
+                        }
+                    }
+                    (_, Operand::Regex(_)) => false,
+                }
+            }
+            Self::Not(filter) => !filter.matches(attributes),
+            Self::And(lhs, rhs) => lhs.matches(attributes) && rhs.matches(attributes),
+            Self::Or(lhs, rhs) => lhs.matches(attributes) || rhs.matches(attributes),
+        }
+    }
+
+    /// Tags, which values are needed to evaluate the expression.
+    pub fn tags(&self) -> Vec<Tag> {
+        match self {
+            Self::Comparison(tag, _, _) => vec![*tag],
+            Self::Not(filter) => filter.tags(),
+            Self::And(lhs, rhs) | Self::Or(lhs, rhs) => {
+                let mut tags = lhs.tags();
+                tags.extend(rhs.tags());
+                tags
+            }
+        }
+    }
+}
+
+/// Compares values as numbers, when both of them are numbers, and as strings otherwise.
+fn compare(value: &str, expected: &str) -> Ordering {
+    match (number(value.trim()), number(expected)) {
+        (Some(value), Some(expected)) => value.total_cmp(&expected),
+        _ => value.cmp(expected),
+    }
+}
+
+/// Parses a finite decimal number, so that `inf`, `NaN` and alike are compared as strings.
+fn number(value: &str) -> Option<f64> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let filter = parser.parse_or()?;
+
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(format!("unexpected `{token}`")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Operator(Operator),
+    And,
+    Or,
+    Not,
+    LeftParen,
+    RightParen,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Word(word) => write!(f, "{word}"),
+            Self::Quoted(quoted) => write!(f, "\"{quoted}\""),
+            Self::Operator(operator) => write!(f, "{operator:?}"),
+            Self::And => write!(f, "&&"),
+            Self::Or => write!(f, "||"),
+            Self::Not => write!(f, "!"),
+            Self::LeftParen => write!(f, "("),
+            Self::RightParen => write!(f, ")"),
+        }
+    }
+}
+
+/// Splits the expression into tokens. Tags in the `(gggg,eeee)` form are kept as single words.
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        let rest = &s[i..];
+        let (token, len) = if c.is_whitespace() {
+            chars.next();
+            continue;
+        } else if rest.starts_with("&&") {
+            (Token::And, 2)
+        } else if rest.starts_with("||") {
+            (Token::Or, 2)
+        } else if rest.starts_with("==") {
+            (Token::Operator(Operator::Eq), 2)
+        } else if rest.starts_with("!=") {
+            (Token::Operator(Operator::Ne), 2)
+        } else if rest.starts_with("<=") {
+            (Token::Operator(Operator::Le), 2)
+        } else if rest.starts_with(">=") {
+            (Token::Operator(Operator::Ge), 2)
+        } else if c == '<' {
+            (Token::Operator(Operator::Lt), 1)
+        } else if c == '>' {
+            (Token::Operator(Operator::Gt), 1)
+        } else if c == '~' {
+            (Token::Operator(Operator::Matches), 1)
+        } else if c == '!' {
+            (Token::Not, 1)
+        } else if c == ')' {
+            (Token::RightParen, 1)
+        } else if c == '(' {
+            // `(gggg,eeee)` is a tag, while any other parenthesis groups the expression
+            match rest.get(..11).filter(|tag| tag.parse::<Tag>().is_ok()) {
+                Some(tag) => (Token::Word(tag.to_string()), 11),
+                None => (Token::LeftParen, 1),
+            }
+        } else if c == '"' {
+            let mut quoted = String::new();
+            let mut escaped = false;
+            let mut len = None;
+            for (j, c) in rest.char_indices().skip(1) {
+                match (escaped, c) {
+                    (false, '\\') => escaped = true,
+                    (false, '"') => {
+                        len = Some(j + 1);
+                        break;
+                    }
+                    (_, c) => {
+                        quoted.push(c);
+                        escaped = false;
+                    }
+                }
+            }
+            let len = len.ok_or_else(|| "unterminated string".to_string())?;
+            (Token::Quoted(quoted), len)
+        } else {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || "&|=!<>~()\"".contains(c))
+                .unwrap_or(rest.len());
+            (Token::Word(rest[..len].to_string()), len)
+        };
+
+        tokens.push(token);
+        while chars.peek().is_some_and(|&(j, _)| j < i + len) {
+            chars.next();
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, where `&&` binds tighter than `||` and `!` binds tighter than both.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            filter = Filter::And(Box::new(filter), Box::new(self.parse_unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_unary(&mut self) -> Result<Filter, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.parse_unary()?))),
+            Some(Token::LeftParen) => {
+                let filter = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RightParen) => Ok(filter),
+                    _ => Err("missing `)`".to_string()),
+                }
+            }
+            Some(Token::Word(tag)) => {
+                let NamedTag { tag, .. } = tag.parse()?;
+                let operator = match self.next() {
+                    Some(Token::Operator(operator)) => operator,
+                    Some(token) => return Err(format!("expected an operator, found `{token}`")),
+                    None => return Err("expected an operator".to_string()),
+                };
+                let value = match self.next() {
+                    Some(Token::Word(value) | Token::Quoted(value)) => value,
+                    Some(token) => return Err(format!("expected a value, found `{token}`")),
+                    None => return Err("expected a value".to_string()),
+                };
+                let operand = if operator == Operator::Matches {
+                    let regex = RegexBuilder::new(&value)
+                        .case_insensitive(true)
+                        .build()
+                        .map_err(|err| err.to_string())?;
+                    Operand::Regex(regex)
+                } else {
+                    Operand::Value(value)
+                };
+
+                Ok(Filter::Comparison(tag, operator, operand))
+            }
+            Some(token) => Err(format!("unexpected `{token}`")),
+            None => Err("unexpected end of the expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::dictionary_std::tags;
+
+    fn attributes() -> Attributes {
+        [
+            (tags::MODALITY, "CT".to_string()),
+            (tags::STUDY_DATE, "20230512".to_string()),
+            (tags::BODY_PART_EXAMINED, "Chest".to_string()),
+            (tags::SERIES_NUMBER, "4".to_string()),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn test_filter_evaluation() {
+        let cases = [
+            (
+                r#"Modality == "CT" && StudyDate >= 20230101 && BodyPartExamined ~ "CHEST""#,
+                true,
+            ),
+            (r#"Modality == "MR" || StudyDate < 20230101"#, false),
+            ("!(Modality == MR) && SeriesNumber < 10", true),
+            ("SeriesNumber > 10", false),
+            ("(0008,0060) != CT || (0018,0015) ~ ^ch", true),
+            ("PatientID == \"\"", true),
+            (
+                "PatientID != \"\" || Modality == CT && SeriesNumber == 5",
+                false,
+            ),
+        ];
+
+        for (expression, expected) in cases {
+            let filter: Filter = expression.parse().unwrap();
+            assert_eq!(filter.matches(&attributes()), expected, "{expression}");
+        }
+    }
+
+    #[test]
+    fn test_only_finite_numbers_are_compared_as_numbers() {
+        assert_eq!(compare("10", "9.5"), Ordering::Greater);
+        assert_eq!(compare(" 1e3", "1000"), Ordering::Equal);
+        assert_eq!(compare("Infinity", "inf"), Ordering::Less);
+        assert_eq!(compare("NaN", "nan"), Ordering::Less);
+        assert_eq!(compare("1e999", "2e999"), Ordering::Less);
+
+        let attributes = [(tags::BODY_PART_EXAMINED, "Inf".to_string())].into();
+        let filter: Filter = "BodyPartExamined > 100".parse().unwrap();
+        assert!(filter.matches(&attributes));
+        let filter: Filter = "BodyPartExamined == INF".parse().unwrap();
+        assert!(!filter.matches(&attributes));
+    }
+
+    #[test]
+    fn test_filter_parsing_errors() {
+        for expression in [
+            "Modality",
+            "Modality ==",
+            "NotATag == 1",
+            "(Modality == CT",
+            "Modality == CT)",
+            "Modality == \"CT",
+            "Modality ~ \"(\"",
+        ] {
+            assert!(expression.parse::<Filter>().is_err(), "{expression}");
+        }
+    }
+}
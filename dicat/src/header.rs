@@ -0,0 +1,247 @@
+use dicom::{
+    core::{dictionary::DataDictionary, dictionary::DataDictionaryEntry, Tag},
+    dictionary_std::{tags, StandardDataDictionary},
+    object::{DefaultDicomObject, OpenFileOptions, ReadError},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    str::FromStr,
+};
+
+/// Tags, which are needed to identify a patient of a DICOM file.
+pub const PATIENT_TAGS: [Tag; 2] = [tags::PATIENT_ID, tags::PATIENT_NAME];
+
+/// Textual values of the requested tags, which are present in a DICOM file.
+pub type Attributes = BTreeMap<Tag, String>;
+
+/// Reads only the header of DICOM files, stopping right after the last of the requested tags
+/// and never reaching the Pixel Data `(7FE0,0010)` element.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::header::{HeaderReader, PATIENT_TAGS};
+/// use dicom::core::Tag;
+///
+/// // Patient ID `(0010,0020)` is the greatest of the patient tags
+/// let reader = HeaderReader::new(PATIENT_TAGS);
+/// assert_eq!(reader.read_until(), Tag(0x0010, 0x0021));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderReader {
+    tags: BTreeSet<Tag>,
+    read_until: Tag,
+}
+
+impl HeaderReader {
+    /// Creates a reader, which stops right after the greatest of the given [`tags`].
+    pub fn new<I: IntoIterator<Item = Tag>>(tags: I) -> Self {
+        let tags: BTreeSet<Tag> = tags.into_iter().collect();
+        let read_until = tags
+            .last()
+            .copied()
+            .map(next_tag)
+            .map_or(tags::PIXEL_DATA, |tag| tag.min(tags::PIXEL_DATA));
+
+        Self { tags, read_until }
+    }
+
+    /// Tags, which values are extracted by [`Self::read`].
+    pub fn tags(&self) -> &BTreeSet<Tag> {
+        &self.tags
+    }
+
+    /// The first tag, which isn't read from the file anymore.
+    pub fn read_until(&self) -> Tag {
+        self.read_until
+    }
+
+    /// Reads the header of the DICOM file at [`path`] and extracts textual values of the requested tags.
+    /// Tags, which are absent in the file or can't be represented as text, are left out.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<Attributes, ReadError> {
+        let obj = self.open(path)?;
+
+        let attributes = self
+            .tags
+            .iter()
+            .filter_map(|&tag| {
+                let element = obj.element_opt(tag).ok().flatten()?;
+                let value = element.to_str().ok()?;
+                Some((tag, value.trim().to_string()))
+            })
+            .collect();
+
+        Ok(attributes)
+    }
+
+    /// Opens the DICOM file at [`path`], reading its elements only until [`Self::read_until`].
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<DefaultDicomObject, ReadError> {
+        OpenFileOptions::new()
+            .read_until(self.read_until)
+            .open_file(path)
+    }
+}
+
+impl Default for HeaderReader {
+    fn default() -> Self {
+        Self::new(PATIENT_TAGS)
+    }
+}
+
+/// A tag requested by the user along with its name, which is its dictionary keyword when it's known.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::header::NamedTag;
+/// use dicom::core::Tag;
+///
+/// let tag: NamedTag = "(0008,0080)".parse().unwrap();
+/// assert_eq!(tag.name, "InstitutionName");
+/// assert_eq!(tag.tag, Tag(0x0008, 0x0080));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedTag {
+    pub name: String,
+    pub tag: Tag,
+}
+
+impl FromStr for NamedTag {
+    type Err = String;
+
+    /// Accepts dictionary keywords, `(gggg,eeee)`, `gggg,eeee` and `ggggeeee`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let tag = StandardDataDictionary
+            .parse_tag(s)
+            .ok_or_else(|| format!("unknown tag `{s}`"))?;
+
+        Ok(Self::from(tag))
+    }
+}
+
+impl From<Tag> for NamedTag {
+    fn from(tag: Tag) -> Self {
+        let name = StandardDataDictionary
+            .by_tag(tag)
+            .map_or_else(|| format!("{tag}"), |entry| entry.alias().to_string());
+
+        Self { name, tag }
+    }
+}
+
+/// Tags separated by `,`. Commas inside of the parentheses of `(gggg,eeee)` don't separate tags.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::header::TagList;
+///
+/// let tags: TagList = "StudyDate,(0008,0080),Modality".parse().unwrap();
+/// let names: Vec<_> = tags.0.iter().map(|tag| tag.name.as_str()).collect();
+/// assert_eq!(names, ["StudyDate", "InstitutionName", "Modality"]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagList(pub Vec<NamedTag>);
+
+impl TagList {
+    pub fn tags(&self) -> impl Iterator<Item = Tag> + '_ {
+        self.0.iter().map(|named_tag| named_tag.tag)
+    }
+}
+
+impl FromIterator<Tag> for TagList {
+    fn from_iter<I: IntoIterator<Item = Tag>>(tags: I) -> Self {
+        Self(tags.into_iter().map(NamedTag::from).collect())
+    }
+}
+
+impl FromStr for TagList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tags = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    tags.push(s[start..i].parse()?);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        tags.push(s[start..].parse()?);
+
+        Ok(Self(tags))
+    }
+}
+
+/// Returns the tag, which immediately follows [`tag`] in the standard DICOM tag ordering.
+fn next_tag(Tag(group, element): Tag) -> Tag {
+    match element.checked_add(1) {
+        Some(element) => Tag(group, element),
+        None => Tag(group.saturating_add(1), 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_reader_stops_before_pixel_data() {
+        let reader = HeaderReader::new([tags::PATIENT_ID, tags::PIXEL_DATA, tags::PIXEL_DATA]);
+        assert_eq!(reader.read_until(), tags::PIXEL_DATA);
+
+        let reader = HeaderReader::new([]);
+        assert_eq!(reader.read_until(), tags::PIXEL_DATA);
+    }
+
+    #[test]
+    fn test_header_reader_reads_patient() {
+        let path = "test_small_dir/1-010.dcm";
+        let header = HeaderReader::default().open(path).unwrap();
+        let full = dicom::object::open_file(path).unwrap();
+
+        for tag in PATIENT_TAGS {
+            assert_eq!(
+                header.element(tag).unwrap().to_str().unwrap(),
+                full.element(tag).unwrap().to_str().unwrap()
+            );
+        }
+        assert!(header.element_opt(tags::PIXEL_DATA).unwrap().is_none());
+        assert!(header
+            .element_opt(tags::STUDY_INSTANCE_UID)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_header_reader_extracts_attributes() {
+        let reader = HeaderReader::new([tags::PATIENT_ID, tags::MODALITY]);
+        let attributes = reader.read("test_small_dir/1-010.dcm").unwrap();
+
+        assert_eq!(attributes[&tags::PATIENT_ID], "CMB-GEC-MSB-06857");
+        assert_eq!(attributes[&tags::MODALITY], "CT");
+        assert!(!attributes.contains_key(&tags::PATIENT_NAME));
+    }
+
+    #[test]
+    fn test_tag_list_parsing() {
+        let tags: TagList = "PatientID, (0010,0010),00080060".parse().unwrap();
+        let tags: Vec<Tag> = tags.tags().collect();
+        assert_eq!(tags, [tags::PATIENT_ID, tags::PATIENT_NAME, tags::MODALITY]);
+
+        assert!("PatientID,NotATag".parse::<TagList>().is_err());
+        assert!("(0010,0010".parse::<TagList>().is_err());
+
+        let tags: TagList = [tags::STUDY_DATE, Tag(0x0009, 0x1001)]
+            .into_iter()
+            .collect();
+        let names: Vec<&str> = tags.0.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, ["StudyDate", "(0009,1001)"]);
+    }
+}
@@ -0,0 +1,306 @@
+use dicom::core::Tag;
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    errors::{CliError, CliResult},
+    header::{Attributes, HeaderReader},
+};
+
+const INDEX_FILE: &str = "index.redb";
+
+/// Environment variable, which overrides the cache directory of the indexes.
+pub const CACHE_DIR_VAR: &str = "DICAT_CACHE_DIR";
+
+/// Table of index entries, keyed by paths relative to the cataloged root.
+const FILES: TableDefinition<&str, &[u8]> = TableDefinition::new("files");
+
+/// What is known about a single file from one of the previous runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexEntry {
+    size: u64,
+    modified: (u64, u32),
+    /// Tags, which were requested when the file was read
+    tags: Vec<u32>,
+    /// Values of the requested tags or [`None`] for files, which aren't valid DICOM files
+    attributes: Option<BTreeMap<u32, String>>,
+}
+
+/// Persistent index of the files in a directory, which lives in the user's cache directory, so that read-only
+/// and shared directories can be indexed and aren't changed by it.
+/// Files, which haven't changed since the last run, aren't read again.
+pub struct Index {
+    db: Database,
+    root: PathBuf,
+    index_dir: PathBuf,
+    entries: HashMap<String, IndexEntry>,
+    seen: Mutex<HashSet<String>>,
+    updates: Mutex<Vec<(String, IndexEntry)>>,
+}
+
+impl Index {
+    /// Opens the index of the directory at [`root`], creating a new one when it doesn't exist yet.
+    pub fn open<A: AsRef<Path>>(root: A) -> CliResult<Self> {
+        let index_dir = index_dir(root.as_ref())?;
+        Self::open_in(root, index_dir)
+    }
+
+    /// Opens the index of the directory at [`root`], which is kept in the [`index_dir`].
+    fn open_in<A: AsRef<Path>>(root: A, index_dir: PathBuf) -> CliResult<Self> {
+        let root = root.as_ref().to_path_buf();
+
+        std::fs::create_dir_all(&index_dir).map_err(index_error(&index_dir))?;
+        let db = Database::create(index_dir.join(INDEX_FILE)).map_err(index_error(&index_dir))?;
+        let entries = load_entries(&db, &index_dir)?;
+
+        Ok(Self {
+            db,
+            root,
+            index_dir,
+            entries,
+            seen: Mutex::default(),
+            updates: Mutex::default(),
+        })
+    }
+
+    /// Returns attributes of the DICOM file at [`path`]. The file is read with [`reader`] only
+    /// when it is new, has changed since the last run or lacks some of the requested tags.
+    /// Returns [`None`] for files, which aren't valid DICOM files.
+    pub fn read(&self, reader: &HeaderReader, path: &Path) -> Option<Attributes> {
+        let key = self.key(path);
+        let metadata = std::fs::metadata(path).ok()?;
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| (modified.as_secs(), modified.subsec_nanos()))
+            .unwrap_or_default();
+
+        self.seen.lock().unwrap().insert(key.clone());
+
+        if let Some(entry) = self.entries.get(&key) {
+            let has_tags = reader
+                .tags()
+                .iter()
+                .all(|&tag| entry.tags.contains(&tag_to_u32(tag)));
+
+            if entry.size == size && entry.modified == modified {
+                match &entry.attributes {
+                    None => return None,
+                    Some(attributes) if has_tags => {
+                        let attributes = attributes
+                            .iter()
+                            .map(|(&tag, value)| (u32_to_tag(tag), value.clone()))
+                            .filter(|(tag, _)| reader.tags().contains(tag))
+                            .collect();
+                        return Some(attributes);
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        let attributes = reader.read(path).ok();
+        let entry = IndexEntry {
+            size,
+            modified,
+            tags: reader.tags().iter().copied().map(tag_to_u32).collect(),
+            attributes: attributes.as_ref().map(|attributes| {
+                attributes
+                    .iter()
+                    .map(|(&tag, value)| (tag_to_u32(tag), value.clone()))
+                    .collect()
+            }),
+        };
+        self.updates.lock().unwrap().push((key, entry));
+
+        attributes
+    }
+
+    /// Stores new and changed entries and drops entries of the files, which weren't seen during this run.
+    pub fn save(self) -> CliResult<()> {
+        let Self {
+            db,
+            root: _,
+            index_dir,
+            entries,
+            seen,
+            updates,
+        } = self;
+        let seen = seen.into_inner().unwrap();
+        let updates = updates.into_inner().unwrap();
+
+        let deleted: Vec<&String> = entries.keys().filter(|key| !seen.contains(*key)).collect();
+        if updates.is_empty() && deleted.is_empty() {
+            return Ok(());
+        }
+
+        let write_txn = db.begin_write().map_err(index_error(&index_dir))?;
+        {
+            let mut table = write_txn
+                .open_table(FILES)
+                .map_err(index_error(&index_dir))?;
+            for (key, entry) in &updates {
+                // Serializing plain data into JSON can't fail
+                let value = serde_json::to_vec(entry).unwrap();
+                table
+                    .insert(key.as_str(), value.as_slice())
+                    .map_err(index_error(&index_dir))?;
+            }
+            for key in deleted {
+                table
+                    .remove(key.as_str())
+                    .map_err(index_error(&index_dir))?;
+            }
+        }
+        write_txn.commit().map_err(index_error(&index_dir))
+    }
+
+    /// Key of the file at [`path`], which doesn't depend on the way the root was referred to.
+    fn key(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Directory of the index of the [`root`] directory.
+fn index_dir(root: &Path) -> CliResult<PathBuf> {
+    cache_dir_of("index", root)
+}
+
+/// Directory of the [`kind`] in the cache directory, which belongs to the [`root`] directory. It's named after
+/// the hash of the canonical path of the root, so that each directory has its own one regardless of the way
+/// it's referred to.
+pub(crate) fn cache_dir_of(kind: &str, root: &Path) -> CliResult<PathBuf> {
+    let canonical = root
+        .canonicalize()
+        .map_err(|_| CliError::DirectoryDoesNotExist(root.to_path_buf()))?;
+    let digest = Sha256::digest(canonical.to_string_lossy().as_bytes());
+    let name: String = digest[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    Ok(cache_dir().join(kind).join(name))
+}
+
+/// Cache directory of the user: [`CACHE_DIR_VAR`], `XDG_CACHE_HOME`, `~/.cache` or `%LOCALAPPDATA%`,
+/// whichever is set first, or the temporary directory otherwise.
+fn cache_dir() -> PathBuf {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+
+    if let Some(dir) = var(CACHE_DIR_VAR) {
+        return PathBuf::from(dir);
+    }
+    var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("dicat")
+}
+
+/// Loads all entries of the index into the memory. Entries, which can't be deserialized, are dropped.
+fn load_entries(db: &Database, index_dir: &Path) -> CliResult<HashMap<String, IndexEntry>> {
+    let read_txn = db.begin_read().map_err(index_error(index_dir))?;
+    let table = match read_txn.open_table(FILES) {
+        Ok(table) => table,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(HashMap::new()),
+        Err(err) => return Err(index_error(index_dir)(err)),
+    };
+
+    let mut entries = HashMap::new();
+    for item in table.iter().map_err(index_error(index_dir))? {
+        let (key, value) = item.map_err(index_error(index_dir))?;
+        if let Ok(entry) = serde_json::from_slice(value.value()) {
+            entries.insert(key.value().to_string(), entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Maps any error of the underlying storage into [`CliError::IndexError`].
+fn index_error<E>(index_dir: &Path) -> impl Fn(E) -> CliError + '_ {
+    move |_| CliError::IndexError(index_dir.to_path_buf())
+}
+
+fn tag_to_u32(Tag(group, element): Tag) -> u32 {
+    (u32::from(group) << 16) | u32::from(element)
+}
+
+fn u32_to_tag(tag: u32) -> Tag {
+    Tag((tag >> 16) as u16, tag as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::PATIENT_TAGS;
+    use dicom::dictionary_std::tags;
+
+    #[test]
+    fn test_index_reads_only_changed_files() -> CliResult<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let dicom_path = dir.path().join("1-010.dcm");
+        let text_path = dir.path().join("not_dicom.txt");
+        std::fs::copy("test_small_dir/1-010.dcm", &dicom_path).unwrap();
+        std::fs::write(&text_path, "not a DICOM file").unwrap();
+
+        let index_dir = tempfile::tempdir().unwrap();
+        let open = || Index::open_in(dir.path(), index_dir.path().to_path_buf());
+
+        let reader = HeaderReader::new(PATIENT_TAGS);
+        let index = open()?;
+        let attributes = index.read(&reader, &dicom_path).unwrap();
+        assert!(index.read(&reader, &text_path).is_none());
+        assert_eq!(index.updates.lock().unwrap().len(), 2);
+        index.save()?;
+
+        // Nothing has changed, so nothing is read again
+        let index = open()?;
+        assert_eq!(index.read(&reader, &dicom_path), Some(attributes));
+        assert!(index.read(&reader, &text_path).is_none());
+        assert!(index.updates.lock().unwrap().is_empty());
+        index.save()?;
+
+        // Tags, which weren't requested before, require reading the file again
+        let reader = HeaderReader::new([tags::PATIENT_ID, tags::MODALITY]);
+        std::fs::remove_file(&text_path).unwrap();
+        let index = open()?;
+        let attributes = index.read(&reader, &dicom_path).unwrap();
+        assert_eq!(attributes[&tags::MODALITY], "CT");
+        assert_eq!(index.updates.lock().unwrap().len(), 1);
+        index.save()?;
+
+        // Deleted files are dropped from the index
+        let index = open()?;
+        assert_eq!(index.entries.len(), 1);
+
+        // The indexed directory itself isn't changed
+        assert!(!dir.path().join(crate::journal::STATE_DIR).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_dir_depends_on_the_canonical_path() -> CliResult<()> {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("a")).unwrap();
+
+        let expected = index_dir(&dir.path().join("a"))?;
+        assert_eq!(index_dir(&dir.path().join("a/../a/."))?, expected);
+        assert_ne!(index_dir(dir.path())?, expected);
+        assert!(expected.starts_with(cache_dir()));
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    fmt,
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Amount of the workers, which start transferring files in the `auto` mode. Has been picked experimentally:
+/// on 'SK hynix PC601 HFS512GD9TNG-L2A0A' SSD less than 4 workers occupy < 100% of possible throughput
+const INITIAL_AUTO_JOBS: usize = 4;
+
+/// Upper bound of the workers in the `auto` mode.
+const MAX_AUTO_JOBS: usize = 32;
+
+/// Relative change of the throughput, which is considered to be noise rather than an effect of the concurrency.
+const THROUGHPUT_TOLERANCE: f64 = 0.05;
+
+/// Amount of the workers, which transfer files concurrently.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::jobs::Jobs;
+///
+/// assert_eq!("8".parse::<Jobs>().unwrap().to_string(), "8");
+/// assert_eq!("auto".parse(), Ok(Jobs::Auto));
+/// assert!("0".parse::<Jobs>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jobs {
+    /// Fixed amount of the workers
+    Fixed(NonZeroUsize),
+    /// Amount of the workers is adjusted according to the measured throughput
+    Auto,
+}
+
+impl FromStr for Jobs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+
+        s.parse()
+            .map(Self::Fixed)
+            .map_err(|_| format!("`{s}` is neither a positive number nor `auto`"))
+    }
+}
+
+impl fmt::Display for Jobs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(jobs) => write!(f, "{jobs}"),
+            Self::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+/// Limits how many of the workers are active at once and counts transferred bytes, which the limit is tuned by.
+/// Each active worker holds one of the [`Concurrency::limit`] permits, while the rest of them wait for one.
+#[derive(Debug)]
+pub struct Concurrency {
+    jobs: Jobs,
+    limit: AtomicUsize,
+    permits: Semaphore,
+    /// Permits beyond the limit, which have been lowered while they were held, so they are forgotten on their return
+    excess: AtomicUsize,
+    bytes: AtomicU64,
+}
+
+impl Concurrency {
+    pub fn new(jobs: Jobs) -> Self {
+        let limit = match jobs {
+            Jobs::Fixed(jobs) => jobs.get(),
+            Jobs::Auto => INITIAL_AUTO_JOBS,
+        };
+
+        Self {
+            jobs,
+            limit: AtomicUsize::new(limit),
+            permits: Semaphore::new(limit),
+            excess: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Amount of the workers, which have to be spawned. Only [`Concurrency::limit`] of them are active at once.
+    pub fn workers(&self) -> usize {
+        match self.jobs {
+            Jobs::Fixed(jobs) => jobs.get(),
+            Jobs::Auto => MAX_AUTO_JOBS,
+        }
+    }
+
+    /// Checks whether the limit is adjusted according to the throughput.
+    pub fn is_adaptive(&self) -> bool {
+        self.jobs == Jobs::Auto
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Waits until one of the workers may take the next file. It may take files, until the [`Permit`] is dropped.
+    pub async fn acquire(&self) -> Permit<'_> {
+        // The semaphore is never closed
+        let permit = self.permits.acquire().await.unwrap();
+        Permit {
+            concurrency: self,
+            permit: Some(permit),
+        }
+    }
+
+    /// Counts [`bytes`], which have been transferred by one of the workers.
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Adjusts the limit according to the throughput over the last [`interval`], which has been tracked by the [`tuner`].
+    pub fn adjust(&self, tuner: &mut Tuner, interval: Duration) {
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let throughput = (bytes - tuner.bytes) as f64 / interval.as_secs_f64();
+        tuner.bytes = bytes;
+
+        let (limit, direction) = climb(self.limit(), tuner.direction, tuner.throughput, throughput);
+        self.set_limit(limit);
+        tuner.direction = direction;
+        tuner.throughput = throughput;
+    }
+
+    /// Adds or removes permits, so that only [`limit`] workers are active at once. Permits, which are held
+    /// by the workers, are removed, once they are returned.
+    fn set_limit(&self, limit: usize) {
+        let previous = self.limit.swap(limit, Ordering::Relaxed);
+        if limit > previous {
+            // Permits, which haven't been returned yet, are kept rather than added anew
+            let added = limit - previous;
+            let kept = self
+                .excess
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |excess| {
+                    Some(excess.saturating_sub(added))
+                })
+                .map_or(0, |excess| excess.min(added));
+            self.permits.add_permits(added - kept);
+        } else {
+            let removed = previous - limit;
+            let forgotten = self.permits.forget_permits(removed);
+            self.excess
+                .fetch_add(removed - forgotten, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Permission of a worker to take files, which is returned to the [`Concurrency`], when it's dropped.
+pub struct Permit<'a> {
+    concurrency: &'a Concurrency,
+    permit: Option<SemaphorePermit<'a>>,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let excess =
+            self.concurrency
+                .excess
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |excess| {
+                    excess.checked_sub(1)
+                });
+        if excess.is_ok() {
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+        }
+    }
+}
+
+/// State of the adjustments, which are made by [`Concurrency::adjust`].
+#[derive(Debug)]
+pub struct Tuner {
+    bytes: u64,
+    throughput: f64,
+    direction: isize,
+}
+
+impl Default for Tuner {
+    fn default() -> Self {
+        // More workers are tried first, since a single SSD usually isn't saturated by the initial amount
+        Self {
+            bytes: 0,
+            throughput: 0.0,
+            direction: 1,
+        }
+    }
+}
+
+/// Hill climbing over the amount of the workers: the [`limit`] keeps changing in the same [`direction`], while
+/// the throughput grows, turns back, when it drops or the limit reaches its bounds, and stays, when the throughput
+/// doesn't change significantly. Returns the next limit and direction.
+fn climb(limit: usize, direction: isize, previous: f64, current: f64) -> (usize, isize) {
+    let direction = if current < previous * (1.0 - THROUGHPUT_TOLERANCE) {
+        -direction
+    } else if current > previous * (1.0 + THROUGHPUT_TOLERANCE) {
+        direction
+    } else {
+        return (limit, direction);
+    };
+
+    let next = limit
+        .saturating_add_signed(direction)
+        .clamp(1, MAX_AUTO_JOBS);
+    if next == limit {
+        (next, -direction)
+    } else {
+        (next, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_climb() {
+        // Growing throughput keeps adding workers
+        assert_eq!(climb(4, 1, 100.0, 150.0), (5, 1));
+        // Dropping throughput turns back
+        assert_eq!(climb(5, 1, 150.0, 120.0), (4, -1));
+        assert_eq!(climb(4, -1, 120.0, 80.0), (5, 1));
+        // Noise doesn't change anything
+        assert_eq!(climb(5, 1, 150.0, 152.0), (5, 1));
+        // The limit stays within bounds
+        assert_eq!(climb(1, -1, 100.0, 200.0), (1, 1));
+        assert_eq!(climb(MAX_AUTO_JOBS, 1, 100.0, 200.0), (MAX_AUTO_JOBS, -1));
+    }
+
+    #[test]
+    fn test_concurrency_adjusts_only_in_auto_mode() {
+        let concurrency = Concurrency::new(Jobs::Auto);
+        assert_eq!(concurrency.workers(), MAX_AUTO_JOBS);
+        assert_eq!(concurrency.permits.available_permits(), INITIAL_AUTO_JOBS);
+
+        let mut tuner = Tuner::default();
+        concurrency.add_bytes(1000);
+        concurrency.adjust(&mut tuner, Duration::from_secs(1));
+        assert_eq!(concurrency.limit(), INITIAL_AUTO_JOBS + 1);
+        assert_eq!(
+            concurrency.permits.available_permits(),
+            INITIAL_AUTO_JOBS + 1
+        );
+
+        let concurrency = Concurrency::new("2".parse().unwrap());
+        assert_eq!(concurrency.workers(), 2);
+        assert!(!concurrency.is_adaptive());
+    }
+
+    #[test]
+    fn test_lowered_limit_takes_back_permits_in_use() {
+        let concurrency = Concurrency::new(Jobs::Auto);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let permits: Vec<Permit> = (0..INITIAL_AUTO_JOBS)
+            .map(|_| runtime.block_on(concurrency.acquire()))
+            .collect();
+
+        // All of the permits are in use, so they are only taken back on their return
+        concurrency.set_limit(1);
+        assert_eq!(concurrency.permits.available_permits(), 0);
+        concurrency.set_limit(2);
+        assert_eq!(concurrency.permits.available_permits(), 0);
+        drop(permits);
+        assert_eq!(concurrency.permits.available_permits(), 2);
+
+        concurrency.set_limit(3);
+        assert_eq!(concurrency.permits.available_permits(), 3);
+    }
+}
@@ -0,0 +1,341 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    anonymization::Profile,
+    checksum::Checksum,
+    errors::{CliError, CliResult},
+    index,
+    plan::Plan,
+    prompt_parser::options::TransferMode,
+    vault,
+};
+
+/// Directory inside of a restructured directory, which contains the [`Run`] and its [`Journal`].
+/// It is never traversed itself.
+pub const STATE_DIR: &str = ".dicat";
+
+/// File in the [`STATE_DIR`] of the restructured directory, or in the private directory of a de-identifying run,
+/// which contains the [`Run`].
+const RUN_FILE: &str = "restruct.json";
+
+/// File in the [`STATE_DIR`] of the restructured directory, which lists destinations of the completed transfers
+/// along with checksums of their sources.
+const JOURNAL_FILE: &str = "journal";
+
+/// Restructuring, which is saved in its root directory, so that it can be resumed after an interruption.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Run {
+    pub mode: TransferMode,
+    #[serde(default)]
+    pub verify: bool,
+    /// De-identification, which is applied to the files instead of the transfer mode
+    #[serde(default)]
+    pub anonymization: Option<Profile>,
+    pub plan: Plan,
+}
+
+/// Append-only list of destinations of the completed transfers, one per line. Each line is written right after
+/// its transfer and consists of the length of the destination's bytes and their hex digits, so that any path,
+/// including the ones with line breaks or invalid UTF-8, fits in a line, while a line, which has been cut
+/// by an interruption, never matches any of the destinations. Verified transfers are followed by the checksum
+/// of their source, so that the manifests of a resumed run list the sources rather than what has been written.
+pub struct Journal {
+    /// Directory, which contains the journal
+    dir: PathBuf,
+    /// File of the journal, until any record fails to be written into it
+    file: Mutex<Option<File>>,
+}
+
+impl Journal {
+    /// Saves the [`run`] in the [`STATE_DIR`] of its root directory and starts an empty journal next to it.
+    /// A de-identifying run is saved in its [`private_dir`] instead, so that its secret and the original paths
+    /// are never left in the de-identified directory, which is handed over to others.
+    pub fn create(run: &Run) -> CliResult<Self> {
+        let dir = match run.anonymization {
+            Some(_) => private_dir(&run.plan.root)?,
+            None => run.plan.root.join(STATE_DIR),
+        };
+        std::fs::create_dir_all(&dir).map_err(journal_error(&dir))?;
+        #[cfg(unix)]
+        if run.anonymization.is_some() {
+            use std::os::unix::fs::PermissionsExt;
+
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+                .map_err(journal_error(&dir))?;
+        }
+
+        // Serializing plain data into JSON can't fail
+        let saved_run = serde_json::to_vec(run).unwrap();
+        // The secret of the de-identification mustn't be readable by anyone, but the owner
+        let create = |name| match run.anonymization {
+            Some(_) => vault::create_private(&dir.join(name), false),
+            None => File::create(dir.join(name)),
+        };
+        let mut run_file = create(RUN_FILE).map_err(journal_error(&dir))?;
+        run_file
+            .write_all(&saved_run)
+            .map_err(journal_error(&dir))?;
+        let file = create(JOURNAL_FILE).map_err(journal_error(&dir))?;
+
+        Ok(Self {
+            dir,
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    /// Loads the run, which has been saved in the [`root`] directory, along with destinations of its completed
+    /// transfers and checksums of their sources. The journal is continued, so that the run can be interrupted
+    /// and resumed again.
+    pub fn resume(root: &Path) -> CliResult<(Run, HashMap<PathBuf, Option<Checksum>>, Self)> {
+        let dir = saved_dir(root)?;
+        let saved_run = std::fs::read(dir.join(RUN_FILE)).map_err(journal_error(&dir))?;
+        let run: Run = serde_json::from_slice(&saved_run).map_err(journal_error(&dir))?;
+
+        let journal_path = dir.join(JOURNAL_FILE);
+        let completed = match File::open(&journal_path) {
+            Ok(file) => BufReader::new(file)
+                .split(b'\n')
+                .map_while(Result::ok)
+                .filter_map(|line| decode_entry(&line))
+                .collect(),
+            Err(_) => HashMap::new(),
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal_path)
+            .map_err(journal_error(&dir))?;
+        // The last line may have been cut by the interruption, so the journal is continued on a new line
+        file.write_all(b"\n").map_err(journal_error(&dir))?;
+
+        let journal = Self {
+            dir,
+            file: Mutex::new(Some(file)),
+        };
+        Ok((run, completed, journal))
+    }
+
+    /// Removes the run and the journal of the [`root`] directory, e.g. when the secret of the run mustn't outlive it.
+    pub fn remove(root: &Path) -> CliResult<()> {
+        let dir = saved_dir(root)?;
+        for file in [RUN_FILE, JOURNAL_FILE] {
+            std::fs::remove_file(dir.join(file)).map_err(journal_error(&dir))?;
+        }
+        // The directory is kept, when it contains any other files
+        let _ = std::fs::remove_dir(&dir);
+        Ok(())
+    }
+
+    /// Records, that a file has been transferred to the [`destination`], along with the [`checksum`] of its source,
+    /// when it has been verified. When a record fails, the error is logged and nothing is recorded anymore,
+    /// as the next record would continue the broken line. Files, which haven't been recorded, are only transferred
+    /// once again on resuming.
+    pub fn record(&self, destination: &Path, checksum: Option<Checksum>) {
+        let line = format!("{}\n", encode_entry(destination, checksum));
+        let mut file = self.file.lock().unwrap();
+        let Some(journal) = file.as_mut() else {
+            return;
+        };
+
+        if let Err(err) = journal.write_all(line.as_bytes()) {
+            log::error!(
+                "{}: {err}, so the rest of the files won't be recorded",
+                CliError::JournalError(self.dir.clone())
+            );
+            *file = None;
+        }
+    }
+
+    /// Writes the records through to the storage, so that they outlive a crash of the system.
+    pub fn sync(&self) {
+        let file = self.file.lock().unwrap();
+        if let Some(Err(err)) = file.as_ref().map(File::sync_data) {
+            log::error!("{}: {err}", CliError::JournalError(self.dir.clone()));
+        }
+    }
+}
+
+/// Private directory of the de-identifying runs into the [`root`] directory, which is only accessible by the user.
+fn private_dir(root: &Path) -> CliResult<PathBuf> {
+    index::cache_dir_of("runs", root).map_err(|_| CliError::JournalError(root.to_path_buf()))
+}
+
+/// Directory, which contains the saved run into the [`root`] directory: its [`STATE_DIR`] or its [`private_dir`].
+fn saved_dir(root: &Path) -> CliResult<PathBuf> {
+    let dir = root.join(STATE_DIR);
+    if dir.join(RUN_FILE).exists() {
+        Ok(dir)
+    } else {
+        private_dir(root)
+    }
+}
+
+/// Line of the journal, which records the [`destination`] and the [`checksum`] of its source, without the line break.
+fn encode_entry(destination: &Path, checksum: Option<Checksum>) -> String {
+    let bytes = path_bytes(destination);
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    match checksum {
+        Some(checksum) => format!("{} {hex} {checksum}", bytes.len()),
+        None => format!("{} {hex}", bytes.len()),
+    }
+}
+
+/// Destination and the checksum of its source, which are recorded in the [`line`] of the journal,
+/// unless the line is incomplete or malformed.
+fn decode_entry(line: &[u8]) -> Option<(PathBuf, Option<Checksum>)> {
+    let line = std::str::from_utf8(line).ok()?;
+    let mut fields = line.trim_end_matches('\r').split(' ');
+    let (len, hex) = (fields.next()?, fields.next()?);
+    if hex.len() != len.parse::<usize>().ok()?.checked_mul(2)? {
+        return None;
+    }
+    let checksum = match (fields.next(), fields.next()) {
+        (None, None) => None,
+        (Some(checksum), None) => Some(checksum.parse().ok()?),
+        _ => return None,
+    };
+
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some((path_from_bytes(bytes)?, checksum))
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+
+    Some(std::ffi::OsString::from_vec(bytes).into())
+}
+
+/// Paths are recorded in their platform's encoding, which is UTF-8 for all of the valid Unicode ones.
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.as_os_str().as_encoded_bytes().to_vec()
+}
+
+/// Paths, which aren't valid Unicode, can't be restored safely, so they are transferred again on resuming.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Maps any error of the underlying storage into [`CliError::JournalError`].
+fn journal_error<E>(dir: &Path) -> impl Fn(E) -> CliError + '_ {
+    move |_| CliError::JournalError(dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::Transfer;
+
+    #[test]
+    fn test_journal_can_be_resumed() -> CliResult<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let transfer = |name: &str| Transfer::new(PathBuf::from(name), dir.path().join(name));
+        let run = Run {
+            mode: TransferMode::Hardlink,
+            verify: true,
+            // The secret of the new UIDs is kept, so that they don't change on resuming
+            anonymization: Some(Profile::new(Default::default())),
+            plan: Plan {
+                root: dir.path().to_path_buf(),
+                transfers: vec![transfer("1.dcm"), transfer("2.dcm"), transfer("3.dcm")],
+                ..Default::default()
+            },
+        };
+
+        let journal = Journal::create(&run).unwrap();
+        // The secret isn't kept in the de-identified directory
+        assert!(!dir.path().join(STATE_DIR).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            let private_dir = private_dir(dir.path())?;
+            assert_eq!(mode(&private_dir), 0o700);
+            assert_eq!(mode(&private_dir.join(RUN_FILE)), 0o600);
+        }
+        let checksum = Checksum::of_bytes(b"1.dcm");
+        journal.record(&dir.path().join("1.dcm"), Some(checksum));
+        // Interrupted in the middle of a record, even right before its checksum
+        let entry = encode_entry(&dir.path().join("2.dcm"), Some(checksum));
+        journal
+            .file
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .write_all(&entry.as_bytes()[..entry.len() - 1])
+            .unwrap();
+        drop(journal);
+
+        let (resumed_run, completed, journal) = Journal::resume(dir.path())?;
+        assert_eq!(resumed_run, run);
+        assert_eq!(
+            completed.get(&dir.path().join("1.dcm")),
+            Some(&Some(checksum))
+        );
+        assert!(!completed.contains_key(&dir.path().join("2.dcm")));
+
+        journal.record(&dir.path().join("2.dcm"), None);
+        journal.sync();
+        drop(journal);
+        let (_, completed, journal) = Journal::resume(dir.path())?;
+        assert_eq!(completed.get(&dir.path().join("2.dcm")), Some(&None));
+        assert!(!completed.contains_key(&dir.path().join("3.dcm")));
+
+        // Line breaks and invalid UTF-8 don't break the records
+        let mut names: Vec<std::ffi::OsString> = vec!["4\n.dcm".into(), "5\r\n.dcm".into()];
+        #[cfg(unix)]
+        names.push(std::os::unix::ffi::OsStringExt::from_vec(
+            b"6\xff.dcm".to_vec(),
+        ));
+        for name in &names {
+            journal.record(&dir.path().join(name), None);
+        }
+        drop(journal);
+        let (_, completed, _) = Journal::resume(dir.path())?;
+        for name in &names {
+            assert!(completed.contains_key(&dir.path().join(name)), "{name:?}");
+        }
+
+        assert!(Journal::resume(&dir.path().join("missing")).is_err());
+        Journal::remove(dir.path())?;
+        assert!(Journal::resume(dir.path()).is_err());
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_journal_stops_recording_after_failure() {
+        // Every write into `/dev/full` fails, as if the storage ran out of space
+        let journal = Journal {
+            dir: PathBuf::from("/dev"),
+            file: Mutex::new(Some(
+                OpenOptions::new().write(true).open("/dev/full").unwrap(),
+            )),
+        };
+
+        journal.record(Path::new("1.dcm"), None);
+        assert!(journal.file.lock().unwrap().is_none());
+        journal.record(Path::new("2.dcm"), None);
+        journal.sync();
+    }
+}
@@ -0,0 +1,535 @@
+use dicom::{core::Tag, dictionary_std::tags};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::header::{Attributes, NamedTag};
+
+/// Layout of the restructured directory, which keeps files of each patient in its own directory.
+pub const DEFAULT_LAYOUT: &str = "{PatientID}/{FileName}";
+
+/// Layout of the de-identified directory, which is made of the new UIDs only.
+pub const ANONYMIZED_LAYOUT: &str = "{StudyInstanceUID}/{SeriesInstanceUID}/{SOPInstanceUID}.dcm";
+
+/// Layout of the pseudonymized directory, which groups the de-identified files by the pseudonyms of the patients.
+pub const PSEUDONYMIZED_LAYOUT: &str =
+    "{PatientID}/{StudyInstanceUID}/{SeriesInstanceUID}/{SOPInstanceUID}.dcm";
+
+/// Placeholder of the original file's name, which isn't a DICOM attribute.
+const FILE_NAME: &str = "FileName";
+
+/// Value of placeholders, which don't have any of their alternatives.
+const MISSING: &str = "UNKNOWN";
+
+/// Characters, which can't be a part of a file name on one of the supported platforms.
+const RESERVED: &[char] = &['/', '\\', '<', '>', ':', '"', '|', '?', '*'];
+
+/// Names of the devices on Windows, which can't be used as file names even with an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Template of the relative path of each restructured file, e.g.
+/// `{PatientID}/{StudyDate}_{StudyDescription}/{SeriesNumber:03}_{Modality}/{InstanceNumber:05}.dcm`.
+///
+/// Each `{...}` placeholder is replaced with the value of a DICOM attribute, which is a dictionary keyword
+/// or `(gggg,eeee)`, while `{FileName}` is replaced with the name of the original file.
+/// Alternatives are separated by `|` and the first present one is used, e.g. `{SeriesDescription|ProtocolName|"unnamed"}`,
+/// where a `"quoted string"` is used as is. Placeholders without any of their alternatives become `UNKNOWN`.
+///
+/// The value can be formatted via one of the specifiers after `:`:
+/// * `0N` pads numbers with zeros up to `N` digits
+/// * `.N` truncates the value to `N` characters
+/// * `upper` and `lower` change the case of the value
+///
+/// Values are percent-encoded, so that they can't escape their path component or be rejected by the file system,
+/// while the original values can still be restored via [`decode`].
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::layout::Layout;
+/// use dicom::dictionary_std::tags;
+/// use std::path::{Path, PathBuf};
+///
+/// let layout: Layout = r#"{PatientID}/{SeriesNumber:03}_{SeriesDescription|"unnamed"}/{FileName}"#
+///     .parse()
+///     .unwrap();
+/// let attributes = [
+///     (tags::PATIENT_ID, "CMB-GEC-MSB-06857".to_string()),
+///     (tags::SERIES_NUMBER, "4".to_string()),
+/// ]
+/// .into();
+/// assert_eq!(
+///     layout.render(Path::new("dir/1-010.dcm"), &attributes),
+///     PathBuf::from("CMB-GEC-MSB-06857/004_unnamed/1-010.dcm")
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Path components, each of which is made of text and placeholders
+    components: Vec<Vec<Segment>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Placeholder(Vec<Alternative>, Format),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Alternative {
+    Tag(Tag),
+    FileName,
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Plain,
+    ZeroPadded(usize),
+    Truncated(usize),
+    Upper,
+    Lower,
+}
+
+impl Layout {
+    /// Tags, which values are needed to render the layout.
+    pub fn tags(&self) -> BTreeSet<Tag> {
+        self.components
+            .iter()
+            .flatten()
+            .filter_map(|segment| match segment {
+                Segment::Placeholder(alternatives, _) => Some(alternatives),
+                Segment::Text(_) => None,
+            })
+            .flatten()
+            .filter_map(|alternative| match alternative {
+                Alternative::Tag(tag) => Some(*tag),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the first component of the layout is a bare `{PatientID}`, so that the top-level directories
+    /// are the encoded IDs of the patients.
+    pub fn starts_with_patient_id(&self) -> bool {
+        match self.components.first().map(Vec::as_slice) {
+            Some([Segment::Placeholder(alternatives, Format::Plain)]) => {
+                alternatives.as_slice() == [Alternative::Tag(tags::PATIENT_ID)]
+            }
+            _ => false,
+        }
+    }
+
+    /// Relative path of the file at [`path`] with the given [`attributes`] in the restructured directory.
+    pub fn render(&self, path: &Path, attributes: &Attributes) -> PathBuf {
+        self.components
+            .iter()
+            .map(|segments| {
+                segments
+                    .iter()
+                    .map(|segment| match segment {
+                        Segment::Text(text) => text.clone(),
+                        Segment::Placeholder(alternatives, format) => {
+                            let value = alternatives
+                                .iter()
+                                .find_map(|alternative| alternative.value(path, attributes))
+                                .unwrap_or_else(|| MISSING.to_string());
+                            encode(&format.apply(value))
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect()
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        DEFAULT_LAYOUT.parse().unwrap()
+    }
+}
+
+impl Alternative {
+    /// Non-empty value of the alternative for the file at [`path`] with the given [`attributes`].
+    fn value(&self, path: &Path, attributes: &Attributes) -> Option<String> {
+        let value = match self {
+            Self::Tag(tag) => attributes.get(tag).cloned(),
+            Self::FileName => path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned()),
+            Self::Text(text) => Some(text.clone()),
+        };
+
+        value.filter(|value| !value.is_empty())
+    }
+}
+
+impl Format {
+    fn apply(self, value: String) -> String {
+        match self {
+            Self::Plain => value,
+            Self::ZeroPadded(width) if value.chars().all(|c| c.is_ascii_digit()) => {
+                format!("{value:0>width$}")
+            }
+            Self::ZeroPadded(_) => value,
+            Self::Truncated(length) => value.chars().take(length).collect(),
+            Self::Upper => value.to_uppercase(),
+            Self::Lower => value.to_lowercase(),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let length = |digits: &str| {
+            digits
+                .parse()
+                .map_err(|_| format!("unknown format specifier `{s}`"))
+        };
+
+        match s {
+            "upper" => Ok(Self::Upper),
+            "lower" => Ok(Self::Lower),
+            _ => match (s.strip_prefix('0'), s.strip_prefix('.')) {
+                (Some(width), _) => Ok(Self::ZeroPadded(length(width)?)),
+                (_, Some(length_digits)) => Ok(Self::Truncated(length(length_digits)?)),
+                _ => Err(format!("unknown format specifier `{s}`")),
+            },
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(['/', '\\']) || Path::new(s).is_absolute() {
+            return Err("layout has to be a relative path".to_string());
+        }
+
+        let components = split_outside_placeholders(s, '/')?
+            .into_iter()
+            .map(parse_component)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if components.iter().any(Vec::is_empty) {
+            return Err("layout can't have empty path components".to_string());
+        }
+        let is_parent_or_current = |segments: &Vec<Segment>| matches!(segments.as_slice(), [Segment::Text(text)] if text == "." || text == "..");
+        if components.iter().any(is_parent_or_current) {
+            return Err("layout can't refer to the current or the parent directory".to_string());
+        }
+
+        Ok(Self { components })
+    }
+}
+
+/// Splits [`s`] on [`separator`], which isn't inside of a placeholder or a quoted string.
+fn split_outside_placeholders(s: &str, separator: char) -> Result<Vec<&str>, String> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_placeholder = false;
+    let mut in_quotes = false;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '{' if !in_quotes => {
+                if in_placeholder {
+                    return Err("placeholders can't be nested".to_string());
+                }
+                in_placeholder = true;
+            }
+            '}' if !in_quotes => {
+                if !in_placeholder {
+                    return Err("unmatched `}`".to_string());
+                }
+                in_placeholder = false;
+            }
+            c if c == separator && !in_placeholder && !in_quotes => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    if in_quotes {
+        return Err("unterminated string".to_string());
+    }
+    if in_placeholder {
+        return Err("unmatched `{`".to_string());
+    }
+    parts.push(&s[start..]);
+
+    Ok(parts)
+}
+
+/// Parses a single path component, which is made of text and `{...}` placeholders.
+fn parse_component(s: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            segments.push(Segment::Text(rest[..start].to_string()));
+        }
+
+        // Placeholders are balanced, which has been checked while splitting the layout
+        let placeholder = &rest[start + 1..];
+        let end = placeholder_end(placeholder);
+        segments.push(parse_placeholder(&placeholder[..end])?);
+        rest = &placeholder[end + 1..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest.to_string()));
+    }
+
+    Ok(segments)
+}
+
+/// Position of the `}`, which closes the placeholder, skipping quoted strings.
+fn placeholder_end(s: &str) -> usize {
+    let mut in_quotes = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '}' if !in_quotes => return i,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+/// Parses the inside of a placeholder, which has the form `Alternative|...|Alternative:format`.
+fn parse_placeholder(s: &str) -> Result<Segment, String> {
+    let mut parts = split_outside_placeholders(s, ':')?;
+    let format = match parts.len() {
+        1 => Format::Plain,
+        2 => parts.pop().unwrap_or_default().trim().parse()?,
+        _ => {
+            return Err(format!(
+                "placeholder `{{{s}}}` has more than one format specifier"
+            ))
+        }
+    };
+
+    let alternatives = split_outside_placeholders(parts[0], '|')?
+        .into_iter()
+        .map(|alternative| {
+            let alternative = alternative.trim();
+            match alternative {
+                "" => Err(format!("placeholder `{{{s}}}` has an empty alternative")),
+                FILE_NAME => Ok(Alternative::FileName),
+                _ => match alternative
+                    .strip_prefix('"')
+                    .and_then(|text| text.strip_suffix('"'))
+                {
+                    Some(text) => Ok(Alternative::Text(text.to_string())),
+                    None => {
+                        let NamedTag { tag, .. } = alternative.parse()?;
+                        Ok(Alternative::Tag(tag))
+                    }
+                },
+            }
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(Segment::Placeholder(alternatives, format))
+}
+
+/// Percent-encodes the [`value`], so that it can be a part of a file name on any of the supported platforms:
+/// `%`, reserved and control characters are always encoded, and so are names of the Windows devices
+/// and trailing dots and spaces, which Windows strips. The latter also covers `.` and `..`.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::layout::{decode, encode};
+///
+/// assert_eq!(encode("98.12.21"), "98.12.21");
+/// assert_eq!(encode("../../etc"), "..%2F..%2Fetc");
+/// assert_eq!(encode(".."), "%2E%2E");
+/// assert_eq!(encode("con.dcm"), "%63on.dcm");
+/// assert_eq!(encode("100%"), "100%25");
+/// assert_eq!(decode(&encode("A\\B: 1.")), "A\\B: 1.");
+/// ```
+pub fn encode(value: &str) -> String {
+    let stem = value.split('.').next().unwrap_or_default();
+    let is_reserved_name = RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(stem.trim_end()));
+    let trailing = value.len() - value.trim_end_matches(['.', ' ']).len();
+
+    let mut encoded = String::with_capacity(value.len());
+    for (i, c) in value.char_indices() {
+        let is_encoded = c == '%'
+            || RESERVED.contains(&c)
+            || c.is_control()
+            || (i == 0 && is_reserved_name)
+            || i >= value.len() - trailing;
+        if is_encoded {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                encoded.push_str(&format!("%{byte:02X}"));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Restores the original value, which has been encoded via [`encode`].
+pub fn decode(name: &str) -> String {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dicom::dictionary_std::tags;
+
+    fn attributes() -> Attributes {
+        [
+            (tags::PATIENT_ID, "CMB-GEC-MSB-06857".to_string()),
+            (tags::STUDY_DATE, "20230512".to_string()),
+            (tags::STUDY_DESCRIPTION, "CT CHEST/ABDOMEN".to_string()),
+            (tags::SERIES_NUMBER, "4".to_string()),
+            (tags::MODALITY, "CT".to_string()),
+            (tags::INSTANCE_NUMBER, "12".to_string()),
+            (tags::SERIES_DESCRIPTION, "".to_string()),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn test_layout_rendering() {
+        let path = Path::new("test_files/dir/56364401.dcm");
+        let cases = [
+            (DEFAULT_LAYOUT, "CMB-GEC-MSB-06857/56364401.dcm"),
+            (
+                "{PatientID}/{StudyDate}_{StudyDescription}/{SeriesNumber:03}_{Modality}/{InstanceNumber:05}.dcm",
+                "CMB-GEC-MSB-06857/20230512_CT CHEST%2FABDOMEN/004_CT/00012.dcm",
+            ),
+            (
+                r#"{SeriesDescription|ProtocolName|"no: description"}/{(0008,0060):lower}"#,
+                "no%3A description/ct",
+            ),
+            (
+                "{PatientID|FileName:.3}/{BodyPartExamined}/{PatientID:upper}",
+                "CMB/UNKNOWN/CMB-GEC-MSB-06857",
+            ),
+            ("{StudyDate:010}/{Modality:03}", "0020230512/CT"),
+        ];
+
+        for (layout, expected) in cases {
+            let layout: Layout = layout.parse().unwrap();
+            assert_eq!(
+                layout.render(path, &attributes()),
+                PathBuf::from(expected),
+                "{layout:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_layout_values_stay_inside_the_root() {
+        let layout: Layout = "{PatientID}/{StudyDescription}".parse().unwrap();
+        let attributes = [
+            (tags::PATIENT_ID, "..".to_string()),
+            (tags::STUDY_DESCRIPTION, "../../etc".to_string()),
+        ]
+        .into();
+
+        assert_eq!(
+            layout.render(Path::new("1.dcm"), &attributes),
+            PathBuf::from("%2E%2E/..%2F..%2Fetc")
+        );
+    }
+
+    #[test]
+    fn test_encoding_is_reversible() {
+        for value in [
+            "CMB-GEC-MSB-06857",
+            "/etc/passwd",
+            "C:\\Windows",
+            "a\0b",
+            "NUL",
+            "lpt1.txt",
+            "COM10",
+            "trailing. ",
+            "%2F",
+            "Пациент 1",
+        ] {
+            let encoded = encode(value);
+            assert!(
+                !encoded.contains(RESERVED) && !encoded.chars().any(char::is_control),
+                "{encoded}"
+            );
+            assert_eq!(decode(&encoded), value);
+        }
+
+        assert_eq!(encode("NUL"), "%4EUL");
+        assert_eq!(encode("COM10"), "COM10");
+        assert_eq!(encode("trailing. "), "trailing%2E%20");
+    }
+
+    #[test]
+    fn test_layout_tags() {
+        let layout: Layout = "{PatientID}/{SeriesDescription|ProtocolName}_{FileName}"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            layout.tags(),
+            [
+                tags::PATIENT_ID,
+                tags::SERIES_DESCRIPTION,
+                tags::PROTOCOL_NAME
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn test_layout_parsing_errors() {
+        for layout in [
+            "/{PatientID}",
+            "{PatientID}//{FileName}",
+            "{PatientID}/../{FileName}",
+            "{PatientID",
+            "PatientID}",
+            "{{PatientID}}",
+            "{NotATag}",
+            "{PatientID|}",
+            "{SeriesNumber:3}",
+            "{SeriesNumber:03:upper}",
+            r#"{SeriesDescription|"unnamed}"#,
+        ] {
+            assert!(layout.parse::<Layout>().is_err(), "{layout}");
+        }
+    }
+}
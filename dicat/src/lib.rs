@@ -1,9 +1,27 @@
 use prompt_parser::{Args, Command};
 use utils::errors::CliResult;
 
+pub mod anonymization;
+pub mod catalog;
+pub mod checksum;
+pub mod filter;
+pub mod header;
+pub mod index;
+pub mod jobs;
+pub mod journal;
+pub mod layout;
+pub mod logging;
 pub mod operation;
+pub mod output;
+pub mod patient;
+pub mod plan;
+pub mod progress;
 pub mod prompt_parser;
+pub mod report;
+pub mod selection;
+pub mod transfer;
 pub mod utils;
+pub mod vault;
 
 pub use utils::errors;
 
@@ -11,7 +29,14 @@ pub struct App;
 
 impl App {
     pub fn start(args: Args) -> CliResult<()> {
-        let Args { command } = args;
+        let Args {
+            command,
+            verbose,
+            quiet,
+            log_file,
+        } = args;
+        logging::init(logging::console_level(verbose, quiet), log_file.as_deref())?;
+
         match command {
             Command::Catalog(catalog_options) => {
                 operation::catalog(catalog_options)?;
@@ -19,6 +44,15 @@ impl App {
             Command::Restruct(restruct_options) => {
                 operation::restruct(restruct_options)?;
             }
+            Command::Verify(verify_options) => {
+                operation::verify(verify_options)?;
+            }
+            Command::Anonymize(anonymize_options) => {
+                operation::anonymize(anonymize_options)?;
+            }
+            Command::Reidentify(reidentify_options) => {
+                operation::reidentify(reidentify_options)?;
+            }
         }
 
         Ok(())
@@ -0,0 +1,78 @@
+use log::{Level, LevelFilter};
+use std::{fmt::Display, path::Path, time::SystemTime};
+
+use crate::errors::{CliError, CliResult};
+
+/// Level of the messages, which are printed to the stderr, according to the amount of `-v` flags and `-q`.
+/// By default warnings and the progress are printed, `-q` leaves only errors.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::logging::console_level;
+/// use log::LevelFilter;
+///
+/// assert_eq!(console_level(0, false), LevelFilter::Info);
+/// assert_eq!(console_level(2, false), LevelFilter::Trace);
+/// assert_eq!(console_level(0, true), LevelFilter::Error);
+/// ```
+pub fn console_level(verbose: u8, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::Error;
+    }
+
+    match verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Sets up the logger, which prints messages to the stderr, and appends them to the [`log_file`] with timestamps.
+/// The file receives debug messages regardless of the console's [`level`].
+pub fn init(level: LevelFilter, log_file: Option<&Path>) -> CliResult<()> {
+    let console = fern::Dispatch::new()
+        .level(level)
+        .format(|out, message, record| match record.level() {
+            Level::Error => out.finish(format_args!("Error: {message}.")),
+            Level::Warn => out.finish(format_args!("Warning: {message}.")),
+            Level::Info => out.finish(format_args!("{message}")),
+            level => out.finish(format_args!("[{level}] {message}")),
+        })
+        .chain(std::io::stderr());
+
+    let mut dispatch = fern::Dispatch::new()
+        // Dependencies log only their own internals, which aren't useful to the user
+        .level(LevelFilter::Warn)
+        .level_for(env!("CARGO_CRATE_NAME"), LevelFilter::Trace)
+        .chain(console);
+
+    if let Some(log_file) = log_file {
+        let file =
+            fern::log_file(log_file).map_err(|_| CliError::LogFileError(log_file.to_path_buf()))?;
+        let file_logger = fern::Dispatch::new()
+            .level(level.max(LevelFilter::Debug))
+            .format(|out, message, record| {
+                out.finish(format_args!(
+                    "{} {:<5} {}: {message}",
+                    humantime::format_rfc3339_millis(SystemTime::now()),
+                    record.level(),
+                    record.target()
+                ))
+            })
+            .chain(file);
+        dispatch = dispatch.chain(file_logger);
+    }
+
+    // The logger can only be set once per process, e.g. the tests run several apps
+    let _ = dispatch.apply();
+    Ok(())
+}
+
+/// Logs the underlying [`err`] along with the [`context`] and maps it into [`CliError::GeneralError`],
+/// which doesn't describe it on its own.
+pub fn general_error<E: Display>(context: &'static str) -> impl FnOnce(E) -> CliError {
+    move |err| {
+        log::error!("{context}: {err}");
+        CliError::GeneralError
+    }
+}
@@ -1,246 +1,1140 @@
-use dicom::{dictionary_std::tags, object::open_file};
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-use prettytable::{format, table};
+use dicom::{dictionary_std::tags, object::ReadError};
+use indicatif::HumanBytes;
+use prettytable::{format, row, table, Table};
 use rayon::iter::{ParallelBridge, ParallelIterator};
+use serde::Serialize;
 use std::{
     borrow::Cow,
-    collections::{HashMap, HashSet},
-    ffi::OsString,
-    fmt::Write,
+    collections::{BTreeSet, HashMap, HashSet},
+    io::Write as _,
     path::{Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use crate::{
+    anonymization::Profile,
+    catalog::{Instance, Patient, Study, HIERARCHY_TAGS},
+    checksum::{self, Checksum},
     errors::{CliError, CliResult},
-    prompt_parser::options::{CatalogOptions, RestructOptions},
+    header::{Attributes, HeaderReader, TagList, PATIENT_TAGS},
+    index::Index,
+    jobs::{Concurrency, Jobs, Tuner},
+    journal::{Journal, Run, STATE_DIR},
+    layout::{Layout, ANONYMIZED_LAYOUT, PSEUDONYMIZED_LAYOUT},
+    logging, output,
+    patient::Identification,
+    plan::{Collision, Plan, Transfer},
+    progress::Progress,
+    prompt_parser::options::{
+        AnonymizeOptions, CatalogFormat, CatalogOptions, CollisionPolicy, PlanFormat,
+        ReidentifyOptions, RestructOptions, TransferMode, VerifyOptions,
+    },
+    report::{Reason, Report},
+    selection::Selection,
+    transfer,
     utils::{Person, SortedPaths},
+    vault::Vault,
 };
 
 /// Catalogs DICOM files in the directory and prints the result to the stdout.
 pub fn catalog(options: CatalogOptions) -> CliResult<()> {
-    let CatalogOptions { path, as_csv, ids } = options;
+    let CatalogOptions {
+        path,
+        as_csv,
+        format,
+        tags,
+        delimiter,
+        no_header,
+        selection,
+        identification,
+        report: report_path,
+        strict,
+    } = options;
 
-    if as_csv {
-        // TODO: Add  Logging of the inner unrepresentable errors
-        traverse_sequentially_and_print_csv(path, ids).map_err(|_err| CliError::GeneralError)?;
-    } else {
-        // Taken from <https://github.com/phsym/prettytable-rs/blob/4d66e6ebddcd52b641369042b68959ad323d9ad0/examples/formatting.rs#L75>
-        let table_format = format::FormatBuilder::new()
-            .column_separator('│')
-            .borders('│')
-            .separators(
-                &[format::LinePosition::Top],
-                format::LineSeparator::new('─', '┬', '┌', '┐'),
-            )
-            .separators(
-                &[format::LinePosition::Intern],
-                format::LineSeparator::new('─', '┼', '├', '┤'),
-            )
-            .separators(
-                &[format::LinePosition::Bottom],
-                format::LineSeparator::new('─', '┴', '└', '┘'),
-            )
-            .padding(1, 1)
-            .build();
-
-        // Get the structure, which can be printed
-        let catalog = scaffold_catalog(path, ids)?;
-
-        // Print the structure using `prettytable::table!`
-        for (person, paths) in catalog {
-            const NOT_LISTED: &str = "[NOT LISTED]";
-            let Person { name, id } = person;
-
-            let name = if name.is_empty() {
-                NOT_LISTED.into()
-            } else {
-                name
+    let report = Report::default();
+    let format = if as_csv { CatalogFormat::Csv } else { format };
+    let index = selection.index;
+    let selection = selection
+        .selection()?
+        .identification(identification.identification());
+
+    match format {
+        CatalogFormat::Table => {
+            // Get the structure, which can be printed
+            let catalog = scaffold_catalog(path.clone(), &selection, index, &report)?;
+            let found_ids = found_ids(&catalog);
+            print_table(catalog);
+            report_missing_ids(&path, &selection, found_ids);
+        }
+        CatalogFormat::Csv => {
+            let tags = tags.unwrap_or_else(|| HIERARCHY_TAGS.into_iter().collect());
+            let csv_options = CsvOptions {
+                delimiter,
+                headers: !no_header,
             };
-            let id = if id.is_empty() { NOT_LISTED.into() } else { id };
+            traverse_sequentially_and_write_csv(
+                path,
+                &selection,
+                index,
+                tags,
+                csv_options,
+                std::io::stdout().lock(),
+                &report,
+            )?;
+        }
+        CatalogFormat::Json => {
+            let catalog = scaffold_catalog(path.clone(), &selection, index, &report)?;
+            let found_ids = found_ids(&catalog);
+            print_json(catalog)?;
+            report_missing_ids(&path, &selection, found_ids);
+        }
+        CatalogFormat::Ndjson => {
+            traverse_sequentially_and_print_ndjson(
+                path,
+                &selection,
+                index,
+                tags.unwrap_or_default(),
+                &report,
+            )?;
+        }
+    }
+
+    report.finish(report_path.as_deref(), strict)
+}
+
+/// Layout of the `.csv` output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CsvOptions {
+    delimiter: u8,
+    headers: bool,
+}
 
-            let id = format!("ID: {}", id.to_string_lossy());
-            let name = format!("Full Name: {}", name.to_string_lossy());
+/// Prints a table for each patient of the [`catalog`] with nested tables for their studies.
+fn print_table(catalog: HashMap<Person, Patient>) {
+    // Taken from <https://github.com/phsym/prettytable-rs/blob/4d66e6ebddcd52b641369042b68959ad323d9ad0/examples/formatting.rs#L75>
+    let table_format = format::FormatBuilder::new()
+        .column_separator('│')
+        .borders('│')
+        .separators(
+            &[format::LinePosition::Top],
+            format::LineSeparator::new('─', '┬', '┌', '┐'),
+        )
+        .separators(
+            &[format::LinePosition::Intern],
+            format::LineSeparator::new('─', '┼', '├', '┤'),
+        )
+        .separators(
+            &[format::LinePosition::Bottom],
+            format::LineSeparator::new('─', '┴', '└', '┘'),
+        )
+        .padding(1, 1)
+        .build();
 
-            let mut table = table!([FG -> id], [FG -> name], [paths]);
-            table.set_format(table_format);
-            table.printstd();
+    // Print the structure using `prettytable::table!`
+    for (person, patient) in catalog {
+        let Person { name, id } = person;
+
+        let name = if name.is_empty() {
+            NOT_LISTED.into()
+        } else {
+            name
+        };
+        let id = if id.is_empty() { NOT_LISTED.into() } else { id };
+
+        let id = format!("ID: {}", id.to_string_lossy());
+        let name = format!("Full Name: {}", name.to_string_lossy());
+        let summary = format!(
+            "Studies: {}, Instances: {}",
+            patient.studies.len(),
+            patient.instance_count()
+        );
+
+        let mut table = table!([FG -> id], [FG -> name], [summary]);
+        for study in patient.studies.values() {
+            table.add_row(row![study_table(study, table_format)]);
         }
+        table.set_format(table_format);
+        table.printstd();
     }
+}
 
-    Ok(())
+/// Prints the [`catalog`] as a JSON array of patients, sorted by their IDs.
+fn print_json(catalog: HashMap<Person, Patient>) -> CliResult<()> {
+    /// Patient's identifying attributes along with their hierarchy and sorted paths of all of their files.
+    #[derive(Serialize)]
+    struct PatientEntry<'a> {
+        #[serde(flatten)]
+        person: &'a Person,
+        instance_count: usize,
+        #[serde(flatten)]
+        patient: &'a Patient,
+        paths: SortedPaths,
+    }
+
+    let mut catalog: Vec<(Person, Patient)> = catalog.into_iter().collect();
+    catalog.sort_by(|(a, _), (b, _)| (&a.id, &a.name).cmp(&(&b.id, &b.name)));
+
+    let entries: Vec<PatientEntry> = catalog
+        .iter()
+        .map(|(person, patient)| PatientEntry {
+            person,
+            instance_count: patient.instance_count(),
+            patient,
+            paths: patient.paths(),
+        })
+        .collect();
+
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &entries)
+        .map_err(logging::general_error("Couldn't print the catalog"))?;
+    writeln!(stdout).map_err(logging::general_error("Couldn't print the catalog"))
+}
+
+/// Placeholder for empty attributes in the printed catalog.
+const NOT_LISTED: &str = "[NOT LISTED]";
+
+/// Builds a nested table for a [`study`], which contains a row for each of its series.
+fn study_table(study: &Study, table_format: format::TableFormat) -> Table {
+    let not_listed = |value: &str| {
+        if value.is_empty() {
+            NOT_LISTED.to_string()
+        } else {
+            value.to_string()
+        }
+    };
+
+    let header = format!(
+        "Study: {} {}",
+        not_listed(&study.date),
+        not_listed(&study.description)
+    );
+    let uid = format!("UID: {}", not_listed(&study.uid));
+    let summary = format!(
+        "Series: {}, Instances: {}",
+        study.series.len(),
+        study.instance_count()
+    );
+
+    let mut series_table = table!([b => "Number", "Modality", "Description", "Instances", "Paths"]);
+    for series in study.series.values() {
+        series_table.add_row(row![
+            not_listed(&series.number),
+            not_listed(&series.modality),
+            not_listed(&series.description),
+            series.instances.len(),
+            series.instances
+        ]);
+    }
+    series_table.set_format(table_format);
+
+    let mut table = table!([FB -> header], [uid], [summary], [series_table]);
+    table.set_format(table_format);
+    table
 }
 
 /// Creates a new directory with restructured structure for each patient, which contains patient's files directly.
 pub fn restruct(options: RestructOptions) -> CliResult<()> {
-    // Amount of tasks spawned for asynchronous copying. Has been picked experimentally at this moment.
-    // On 'SK hynix PC601 HFS512GD9TNG-L2A0A' SSD less than 4 tasks occupy < 100% of possible throughput
-    const TASKS_AMOUNT: usize = 4;
-    let RestructOptions { path, ids } = options;
-    let catalog = scaffold_catalog(path, ids)?;
-    let catalog: HashMap<Person, Vec<PathBuf>> = catalog
-        .into_iter()
-        .map(|(x, paths)| (x, paths.into_inner()))
-        .collect();
+    let RestructOptions {
+        path,
+        output,
+        layout,
+        on_collision,
+        mode,
+        verify,
+        jobs,
+        dry_run,
+        plan_format,
+        plan: plan_file,
+        resume,
+        selection,
+        identification,
+        report: report_path,
+        strict,
+    } = options;
 
-    if !catalog.is_empty() {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    let report = Report::default();
 
-        // Create `root` directory
-        let new_root = format!("dicat_{}", timestamp);
-        let new_root_path = PathBuf::from(new_root);
+    // An interrupted restructuring is continued with its own plan and mode
+    if let Some(root) = resume {
+        resume_restruct(&root, jobs, &report)?;
+        return report.finish(report_path.as_deref(), strict);
+    }
 
-        std::fs::create_dir(&new_root_path)
-            .map_err(|_| CliError::CreatingDirectoryError(PathBuf::from(&new_root_path)))?;
+    // A saved plan is executed as is, without traversing the directory again
+    if let Some(plan_file) = plan_file {
+        let mut plan = Plan::read(&plan_file)?;
+        plan.recheck();
+        let run = Run {
+            mode,
+            verify,
+            anonymization: None,
+            plan,
+        };
+        restruct_by_plan(run, jobs, dry_run, plan_format, &report)?;
+        return report.finish(report_path.as_deref(), strict);
+    }
+
+    // The path is required by the argument parser, unless a plan is provided
+    let path = path.unwrap_or_default();
+    let index = selection.index;
+    let selection = selection
+        .selection()?
+        .identification(identification.identification());
+    // Besides patient's and selection attributes, only the ones used by the layout are needed,
+    // along with `SOPInstanceUID`, which may be used to resolve collisions
+    let header_reader = HeaderReader::new(
+        PATIENT_TAGS
+            .into_iter()
+            .chain([tags::SOP_INSTANCE_UID])
+            .chain(layout.tags())
+            .chain(selection.tags()),
+    );
+    let files = traverse_in_parallel(path.clone(), &selection, index, &header_reader, &report)?;
 
-        // For each person, create `root/person_id` directory
-        for person in catalog.keys() {
-            let mut persons_path = new_root_path.clone();
-            persons_path.push(PathBuf::from(&person.id));
-            std::fs::create_dir(&persons_path)
-                .map_err(|_| CliError::CreatingDirectoryError(PathBuf::from(&persons_path)))?;
+    if !files.is_empty() {
+        let new_root = output.is_none();
+        let root = output_root(output)?;
+
+        // Place each file according to the layout, e.g. into `root/person_id` directory,
+        // before anything is transferred, so that all of the collisions are known beforehand
+        let started = Instant::now();
+        let mut plan = Plan::new(&files, &root, &layout, on_collision);
+        plan.new_root = new_root;
+        log::debug!(
+            "Planned {} transfers in {:.2?}",
+            plan.transfers.len(),
+            started.elapsed()
+        );
+        let run = Run {
+            mode,
+            verify,
+            anonymization: None,
+            plan,
+        };
+        restruct_by_plan(run, jobs, dry_run, plan_format, &report)?;
+    }
+
+    report_missing_ids(&path, &selection, patient_ids(&files));
+
+    report.finish(report_path.as_deref(), strict)
+}
+
+/// Copies DICOM files of the selected patients into a new directory, de-identifying them according to
+/// the DICOM PS3.15 Basic Profile along with the retained options.
+pub fn anonymize(options: AnonymizeOptions) -> CliResult<()> {
+    let AnonymizeOptions {
+        path,
+        output,
+        retain,
+        vault: vault_path,
+        jobs,
+        selection,
+        report: report_path,
+        strict,
+    } = options;
+
+    let report = Report::default();
+    let index = selection.index;
+    let selection = selection
+        .selection()?
+        // Files without an ID are left without it, so that they aren't pseudonymized as a substituted patient
+        .identification(Identification::unidentified());
+    let layout: Layout = match vault_path {
+        Some(_) => PSEUDONYMIZED_LAYOUT.parse().unwrap(),
+        None => ANONYMIZED_LAYOUT.parse().unwrap(),
+    };
+    // The vault is opened before the traversal, so that a vault, which is accessible by others, fails early
+    let mut vault = vault_path
+        .as_deref()
+        .map(Vault::open_or_create)
+        .transpose()?;
+    let header_reader = HeaderReader::new(
+        PATIENT_TAGS
+            .into_iter()
+            .chain(layout.tags())
+            .chain(selection.tags()),
+    );
+    let files = traverse_in_parallel(path.clone(), &selection, index, &header_reader, &report)?;
+
+    if !files.is_empty() {
+        let new_root = output.is_none();
+        let root = output_root(output)?;
+        let retained = retain.into_iter().collect();
+        let profile = match &vault {
+            Some(vault) => Profile::pseudonymizing(retained, vault.key()),
+            None => Profile::new(retained),
+        };
+
+        // Destinations are made of the de-identified attributes, so that the paths don't refer to the patients
+        let anonymized: Vec<(PathBuf, Attributes)> = files
+            .iter()
+            .map(|(path, attributes)| (path.clone(), profile.apply_to_attributes(attributes)))
+            .collect();
+        // Pseudonyms are recorded before any of the files are written, so that each of them can be re-identified
+        if let (Some(vault), Some(vault_path)) = (&mut vault, &vault_path) {
+            for ((_, original), (_, pseudonymized)) in files.iter().zip(&anonymized) {
+                vault.record(original, pseudonymized);
+            }
+            vault.save(vault_path)?;
         }
+        let mut plan = Plan::new(&anonymized, &root, &layout, CollisionPolicy::Suffix);
+        plan.new_root = new_root;
+        let run = Run {
+            mode: TransferMode::Copy,
+            verify: false,
+            anonymization: Some(profile),
+            plan,
+        };
+        restruct_by_plan(run, jobs, false, PlanFormat::default(), &report)?;
+    }
 
-        tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .expect("Failed building the Runtime")
-            .block_on(async move {
-                // Copy corresponding .DICOM files into newely created directories
-                // asynchrnously in `TASK_AMOUNT` tasks
-                copy_files_in_tasks(catalog, TASKS_AMOUNT, &new_root_path).await;
-            });
+    report_missing_ids(&path, &selection, patient_ids(&files));
+
+    report.finish(report_path.as_deref(), strict)
+}
+
+/// Prints original values of the pseudonyms, which have been recorded in the vault, as a .CSV table.
+/// Each re-identification is recorded in the audit log next to the vault along with its reason.
+pub fn reidentify(options: ReidentifyOptions) -> CliResult<()> {
+    let ReidentifyOptions {
+        vault: vault_path,
+        reason,
+        pseudonyms,
+    } = options;
+
+    let vault = Vault::open(&vault_path)?;
+    Vault::audit(&vault_path, &reason, &pseudonyms)?;
+
+    let mapping: Vec<(&str, &str)> = if pseudonyms.is_empty() {
+        vault.mapping().collect()
+    } else {
+        pseudonyms
+            .iter()
+            .filter_map(|pseudonym| match vault.reidentify(pseudonym) {
+                Some(original) => Some((pseudonym.as_str(), original)),
+                None => {
+                    log::warn!("{pseudonym} isn't recorded in the vault");
+                    None
+                }
+            })
+            .collect()
+    };
+
+    let mut writer = csv::Writer::from_writer(std::io::stdout().lock());
+    for record in [("Pseudonym", "Original")].into_iter().chain(mapping) {
+        writer
+            .serialize(record)
+            .map_err(logging::general_error("Couldn't print the mapping"))?;
+    }
+    writer
+        .flush()
+        .map_err(logging::general_error("Couldn't print the mapping"))
+}
+
+/// Directory, which the files are restructured into: the [`output`] one, which has to exist, or a new one.
+fn output_root(output: Option<PathBuf>) -> CliResult<PathBuf> {
+    match output {
+        Some(output) if !output.exists() => Err(CliError::DirectoryDoesNotExist(output)),
+        Some(output) => Ok(output),
+        None => Ok(output::default_root()),
+    }
+}
+
+/// Transfers files according to the plan of the [`run`] or only prints the plan, when it's a [`dry_run`].
+fn restruct_by_plan(
+    run: Run,
+    jobs: Jobs,
+    dry_run: bool,
+    plan_format: PlanFormat,
+    report: &Report,
+) -> CliResult<()> {
+    let Run {
+        mode,
+        verify,
+        anonymization,
+        plan,
+    } = run;
+    check_transfers(&plan.root, &plan.transfers, plan.bytes, mode, dry_run)?;
+
+    if dry_run {
+        // The printed plan can be replayed from any directory
+        let plan = plan.absolute();
+        print_plan(&plan, plan_format)
+            .map_err(logging::general_error("Couldn't print the plan"))?;
+        report_collisions(&plan.collisions);
+        return Ok(());
+    }
+    if plan.has_failed() {
+        report_collisions(&plan.collisions);
+        return Err(CliError::FilesCollide(plan.root));
     }
 
+    // Create `root` directory, unless an existing one has been provided. Its name may have been taken by another run
+    // since the plan has been made, so the destinations are moved into the directory, which has been created instead
+    let mut plan = plan;
+    if plan.new_root || !plan.root.exists() {
+        let root = output::create_unique_root(&plan.root)?;
+        plan = plan.rebase(&root);
+    }
+    // De-identified directories mustn't refer to the original IDs
+    if anonymization.is_none() && !plan.patients.is_empty() {
+        output::write_patients(&plan.root, &plan.patients)?;
+    }
+
+    // The run is saved with absolute paths, so that it can be resumed from any directory
+    let root = plan.root.clone();
+    let run = Run {
+        mode,
+        verify,
+        anonymization,
+        plan: plan.absolute(),
+    };
+    let journal = Journal::create(&run)?;
+    let Run {
+        anonymization,
+        plan:
+            Plan {
+                transfers,
+                collisions,
+                bytes,
+                ..
+            },
+        ..
+    } = run;
+
+    let settings = TransferSettings {
+        mode,
+        verify,
+        anonymization: anonymization.map(Arc::new),
+        jobs,
+    };
+    execute_transfers(&root, transfers, bytes, settings, journal, report)?;
+    report_collisions(&collisions);
     Ok(())
 }
 
-/// Asynchronously in [`num_tasks`] tokio tasks copies .DICOM files into a new `dicat_(timestamp)/(person.id)` directory.
-async fn copy_files_in_tasks(
-    file_map: HashMap<Person, Vec<PathBuf>>,
-    num_tasks: usize,
-    root_path: &Path,
-) {
-    let mut task_handles = Vec::with_capacity(num_tasks);
-    let mut chunk_sizes = vec![0; num_tasks];
+/// Continues the restructuring into the [`root`], which has been interrupted, by transferring only the files,
+/// which haven't been recorded in its [`Journal`].
+fn resume_restruct(root: &Path, jobs: Jobs, report: &Report) -> CliResult<()> {
+    let (
+        Run {
+            mode,
+            verify,
+            anonymization,
+            plan,
+        },
+        completed,
+        journal,
+    ) = Journal::resume(root)?;
+
+    let mut pending = Vec::new();
+    let mut checksums = Vec::new();
+    for transfer in plan.transfers {
+        if let Some(&checksum) = completed.get(&transfer.destination) {
+            // Completed files have already been verified against their sources, which checksums are recorded.
+            // A record, which has been cut right before its checksum, only leaves the checksum of the destination
+            if verify {
+                let checksum = match checksum {
+                    Some(checksum) => checksum,
+                    None => Checksum::of_file(&transfer.destination)
+                        .map_err(|_| CliError::ChecksumsError(root.to_path_buf()))?,
+                };
+                checksums.push((transfer.destination, checksum));
+            }
+            continue;
+        }
+        // A file could have been moved right before the interruption, without being recorded. Its source is only
+        // removed after the whole file has been transferred, so the destination has the content of the source
+        if mode == TransferMode::Move && !transfer.source.exists() && transfer.destination.exists()
+        {
+            let checksum = if verify {
+                Some(
+                    Checksum::of_file(&transfer.destination)
+                        .map_err(|_| CliError::ChecksumsError(root.to_path_buf()))?,
+                )
+            } else {
+                None
+            };
+            journal.record(&transfer.destination, checksum);
+            if let Some(checksum) = checksum {
+                checksums.push((transfer.destination, checksum));
+            }
+            continue;
+        }
+        // Leftovers of the interrupted transfers are replaced
+        if std::fs::symlink_metadata(&transfer.destination).is_ok() {
+            std::fs::remove_file(&transfer.destination)
+                .map_err(|_| CliError::RemovingFileError(transfer.destination.clone()))?;
+        }
+        pending.push(transfer);
+    }
+
+    // Manifests are written at the end of the run, so they may not contain the completed files yet
+    if verify {
+        checksum::write_manifests(root, &checksums)
+            .map_err(|_| CliError::ChecksumsError(root.to_path_buf()))?;
+    }
+    if pending.is_empty() {
+        journal.sync();
+        log::info!(
+            "Nothing to resume, all of the files have already been {}",
+            mode.done()
+        );
+        return Ok(());
+    }
+
+    let bytes = pending.iter().map(|transfer| transfer.size).sum();
+    check_transfers(&plan.root, &pending, bytes, mode, false)?;
+    let settings = TransferSettings {
+        mode,
+        verify,
+        anonymization: anonymization.map(Arc::new),
+        jobs,
+    };
+    execute_transfers(root, pending, bytes, settings, journal, report)
+}
+
+/// Checks, that the [`transfers`] of [`bytes`] in total fit into the [`root`], where only the files, which are copied,
+/// take space. A [`dry_run`] doesn't write anything into the destination, so it only warns about the problems,
+/// which would fail the transfers.
+fn check_transfers(
+    root: &Path,
+    transfers: &[Transfer],
+    bytes: u64,
+    mode: TransferMode,
+    dry_run: bool,
+) -> CliResult<()> {
+    // The root may not exist yet, so the destination is checked via its closest existing ancestor
+    let destination = output::existing_ancestor(root);
+
+    // Files can be moved or linked without copying their content only within a single file system,
+    // while the restructured files may come from several ones
+    let copied: Vec<&Transfer> = if mode.needs_same_file_system() {
+        transfers
+            .iter()
+            .filter(|transfer| !transfer::same_file_system(&transfer.source, &destination))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if !copied.is_empty() {
+        log::warn!(
+            "{} of the files aren't on the same file system as {}, so they will be copied instead of being {}",
+            copied.len(),
+            destination.display(),
+            mode.done()
+        );
+    }
+
+    let needed = if mode.needs_space(true) {
+        bytes
+    } else if mode.needs_space(false) {
+        copied.iter().map(|transfer| transfer.size).sum()
+    } else {
+        0
+    };
+    if dry_run {
+        output::preview_destination(&destination, needed)?;
+    } else {
+        output::check_destination(&destination, needed)?;
+    }
+    Ok(())
+}
+
+/// How the files are transferred by [`execute_transfers`].
+#[derive(Clone)]
+struct TransferSettings {
+    mode: TransferMode,
+    verify: bool,
+    /// De-identification, which replaces the transfer mode
+    anonymization: Option<Arc<Profile>>,
+    jobs: Jobs,
+}
 
-    let files_amount = file_map
+/// Creates directories for the [`transfers`] of [`bytes`] in total and executes them, recording each completed one in the [`journal`].
+/// When the transfers are verified, their checksums are written into the manifests of the patient's directories.
+fn execute_transfers(
+    root: &Path,
+    transfers: Vec<Transfer>,
+    bytes: u64,
+    settings: TransferSettings,
+    journal: Journal,
+    report: &Report,
+) -> CliResult<()> {
+    let TransferSettings { mode, verify, .. } = settings;
+    let anonymized = settings.anonymization.is_some();
+
+    // Create all of the directories of the layout beforehand
+    let directories: BTreeSet<&Path> = transfers
         .iter()
-        .fold(0, |acc, (_person, paths)| acc + paths.len());
+        .filter_map(|transfer| transfer.destination.parent())
+        .collect();
+    for directory in directories {
+        std::fs::create_dir_all(directory)
+            .map_err(|_| CliError::CreatingDirectoryError(PathBuf::from(directory)))?;
+    }
 
-    let files_per_task = files_amount / num_tasks;
-    let remainder = files_amount % num_tasks;
+    let journal = Arc::new(journal);
+    let recording = Arc::clone(&journal);
+    let started = Instant::now();
+    let outcome = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed building the Runtime")
+        .block_on(async move {
+            // Copy corresponding .DICOM files into newely created directories
+            // asynchrnously in `jobs` tasks
+            copy_files_in_tasks(transfers, bytes, root, settings, recording).await
+        });
+    journal.sync();
+    log::debug!("Transferred files in {:.2?}", started.elapsed());
+    if outcome.copied_instead > 0 {
+        log::warn!(
+            "{} files couldn't be {} within their file system, so they have been copied instead",
+            outcome.copied_instead,
+            mode.done()
+        );
+    }
 
-    // Distribute file chunks evenly between tasks
-    for (i, chunk) in chunk_sizes.iter_mut().enumerate().take(num_tasks) {
-        *chunk = files_per_task + if i < remainder { 1 } else { 0 };
+    for (source, message) in outcome.failed {
+        report.add(source, Reason::TransferFailed, message);
+    }
+    for destination in outcome.mismatched {
+        report.add(destination, Reason::ChecksumMismatch, "checksums differ");
+    }
+    if verify {
+        let started = Instant::now();
+        checksum::write_manifests(root, &outcome.checksums)
+            .map_err(|_| CliError::ChecksumsError(root.to_path_buf()))?;
+        log::debug!("Wrote checksums in {:.2?}", started.elapsed());
+    }
+    // The journal refers to the original files and keeps the secret of the new UIDs, so it's only kept
+    // in the de-identified directory, while the run can still be resumed
+    if anonymized && report.failed() == 0 {
+        Journal::remove(root)?;
     }
 
-    let mut pairs_iter = file_map
-        .into_iter()
-        .flat_map(|(to, from)| from.into_iter().zip(std::iter::repeat(to)));
+    Ok(())
+}
 
-    // Spawn new `tokio` task per chunk of paths and copy them to newely created locations
-    for chunk_size in chunk_sizes {
-        let root_path_owned = PathBuf::from(root_path);
-        let files_to_copy: Vec<(PathBuf, Person)> = pairs_iter.by_ref().take(chunk_size).collect();
+/// Checks files of the restructured directory against the checksums in its manifests.
+pub fn verify(options: VerifyOptions) -> CliResult<()> {
+    let VerifyOptions { path } = options;
+    if !path.is_dir() {
+        return Err(CliError::DirectoryDoesNotExist(path));
+    }
+
+    let started = Instant::now();
+    let verification =
+        checksum::verify_directory(&path).map_err(|_| CliError::ChecksumsError(path.clone()))?;
+    log::debug!("Verified checksums in {:.2?}", started.elapsed());
+    if verification.verified == 0 && !verification.has_failed() {
+        return Err(CliError::NoChecksums(path));
+    }
+
+    for file in &verification.mismatched {
+        log::warn!("{} doesn't match its checksum", file.display());
+    }
+    for file in &verification.missing {
+        log::warn!("{} is missing", file.display());
+    }
+    println!(
+        "{} files match their checksums in '{}'",
+        verification.verified,
+        path.to_string_lossy()
+    );
+
+    if verification.has_failed() {
+        Err(CliError::VerificationFailed(path))
+    } else {
+        Ok(())
+    }
+}
+
+/// Prints the [`plan`] to the stdout in the given [`format`].
+fn print_plan(plan: &Plan, format: PlanFormat) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+
+    match format {
+        PlanFormat::Text => {
+            for Transfer {
+                source,
+                destination,
+                ..
+            } in &plan.transfers
+            {
+                writeln!(stdout, "{} -> {}", source.display(), destination.display())?;
+            }
+
+            writeln!(stdout)?;
+            writeln!(stdout, "Directories to create:")?;
+            for directory in &plan.directories {
+                writeln!(stdout, "{}", directory.display())?;
+            }
+
+            writeln!(stdout)?;
+            writeln!(
+                stdout,
+                "Total: {} files, {}",
+                plan.transfers.len(),
+                HumanBytes(plan.bytes)
+            )?;
+        }
+        PlanFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(stdout);
+            writer.write_record(["Kind", "Source", "Destination", "Details"])?;
+            for transfer in &plan.transfers {
+                writer.write_record([
+                    "file".as_bytes(),
+                    transfer.source.as_os_str().as_encoded_bytes(),
+                    transfer.destination.as_os_str().as_encoded_bytes(),
+                    b"",
+                ])?;
+            }
+            for directory in &plan.directories {
+                writer.write_record([
+                    "directory".as_bytes(),
+                    b"",
+                    directory.as_os_str().as_encoded_bytes(),
+                    b"",
+                ])?;
+            }
+            for collision in &plan.collisions {
+                writer.write_record([
+                    "collision".as_bytes(),
+                    collision.source.as_os_str().as_encoded_bytes(),
+                    collision.destination.as_os_str().as_encoded_bytes(),
+                    collision.to_string().as_bytes(),
+                ])?;
+            }
+            writer.write_record(["total", "", "", &plan.bytes.to_string()])?;
+            writer.flush()?;
+        }
+        PlanFormat::Json => {
+            serde_json::to_writer_pretty(&mut stdout, plan)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Warns about each of the files, which destination has been taken by another file.
+fn report_collisions(collisions: &[Collision]) {
+    for collision in collisions {
+        log::warn!("{collision}");
+    }
+}
+
+/// IDs of the patients of the selected [`files`].
+fn patient_ids(files: &[(PathBuf, Attributes)]) -> impl Iterator<Item = &str> {
+    files
+        .iter()
+        .filter_map(|(_, attributes)| attributes.get(&tags::PATIENT_ID))
+        .map(String::as_str)
+}
+
+/// IDs of the cataloged patients.
+fn found_ids(catalog: &HashMap<Person, Patient>) -> Vec<String> {
+    catalog
+        .keys()
+        .map(|person| person.id.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Warns about each of the explicitly listed person IDs, which have no selected files in the directory at [`path`].
+fn report_missing_ids<I, S>(path: &Path, selection: &Selection, found_ids: I)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    for id in selection.missing_ids(found_ids) {
+        log::warn!(
+            "{}",
+            CliError::FilesDoNotExistForPerson(path.to_path_buf(), id)
+        );
+    }
+}
+
+/// Asynchronously transfers .DICOM files of [`bytes`] in total to their destinations inside of the new [`root_path`]
+/// directory according to the [`settings`]. Tokio tasks pull the files from a shared queue, while the jobs limit
+/// how many of them are active at once. Returns what has happened to the transferred files.
+async fn copy_files_in_tasks(
+    transfers: Vec<Transfer>,
+    bytes: u64,
+    root_path: &Path,
+    settings: TransferSettings,
+    journal: Arc<Journal>,
+) -> TransfersOutcome {
+    // Interval, over which the throughput is measured in the `auto` mode
+    const ADJUSTMENT_INTERVAL: Duration = Duration::from_millis(500);
+
+    let TransferSettings {
+        mode,
+        verify,
+        anonymization,
+        jobs,
+    } = settings;
+    let done = if anonymization.is_some() {
+        "anonymized"
+    } else {
+        mode.done()
+    };
+
+    log::info!("Restructuring...");
+    // Destinations in the plan are absolute, so the root is too, to find patient's directories of them
+    let root = Arc::new(std::path::absolute(root_path).unwrap_or_else(|_| root_path.to_path_buf()));
+    let progress = Arc::new(Progress::transfer(transfers.len() as u64, bytes));
+    let queue = Arc::new(Mutex::new(transfers.into_iter()));
+    let concurrency = Arc::new(Concurrency::new(jobs));
+
+    // Spawn `tokio` tasks, which take files from the queue one by one, so that a slow file doesn't stall the rest
+    let mut task_handles = Vec::with_capacity(concurrency.workers());
+    for _ in 0..concurrency.workers() {
+        let root = Arc::clone(&root);
+        let progress = Arc::clone(&progress);
+        let queue = Arc::clone(&queue);
+        let concurrency = Arc::clone(&concurrency);
+        let journal = Arc::clone(&journal);
+        let anonymization = anonymization.clone();
 
         let handle = tokio::spawn(async move {
-            let files_in_chunk = files_to_copy.len();
+            let mut outcome = TransfersOutcome::default();
 
-            for (path_buf, person) in files_to_copy {
-                let mut persons_path = root_path_owned.clone();
-                persons_path.push(&person.id);
+            loop {
+                // Inactive tasks wait for a permit, which is returned at the end of each iteration
+                let _permit = concurrency.acquire().await;
+                // The lock is released right after taking the next file
+                let Some(Transfer {
+                    source,
+                    destination,
+                    size,
+                }) = queue.lock().unwrap().next()
+                else {
+                    break;
+                };
 
-                let filename = path_buf.file_name().unwrap().to_string_lossy(); // TODO: Remove unwrap()
-                let filename_pathbuf = PathBuf::from(filename.as_ref());
-                persons_path.push(&filename_pathbuf);
+                if let Some(patient) = output::patient_directory(&root, &destination).file_name() {
+                    progress.set_patient(&patient.to_string_lossy());
+                }
+                // Sources may be spread over several file systems, so each of them is checked on its own
+                let same_file_system = anonymization.is_some()
+                    || !mode.needs_same_file_system()
+                    || transfer::same_file_system(&source, &root);
+                let transferred = match &anonymization {
+                    Some(profile) => {
+                        transfer::anonymize(Arc::clone(profile), &source, &destination).await
+                    }
+                    None => {
+                        transfer::transfer(mode, &source, &destination, same_file_system, verify)
+                            .await
+                    }
+                };
+                let transferred = match transferred {
+                    Ok(transferred) => transferred,
+                    Err(err) => {
+                        log::error!("{} couldn't be {done}: {err}", source.display());
+                        // A partial copy is removed, as long as the source is still there
+                        if source.exists() {
+                            let _ = tokio::fs::remove_file(&destination).await;
+                        }
+                        outcome.failed.push((source, err.to_string()));
+                        progress.inc(0);
+                        continue;
+                    }
+                };
+                // The progress counts the sizes of the sources, which its total consists of
+                concurrency.add_bytes(size);
+                progress.inc(size);
 
-                tokio::fs::copy(&path_buf, &persons_path).await.unwrap(); // TODO: Remove unwrap()
+                // Files from other file systems have been announced before the transfers
+                if !transferred.in_mode && same_file_system {
+                    outcome.copied_instead += 1;
+                }
+                // Mismatched files aren't recorded, so that they are transferred once again on resuming
+                if transferred.mismatched() {
+                    log::error!(
+                        "{} doesn't match its source {}",
+                        destination.display(),
+                        source.display()
+                    );
+                    outcome.mismatched.push(destination);
+                    continue;
+                }
+                log::trace!(
+                    "{} has been {done} into {}",
+                    source.display(),
+                    destination.display()
+                );
+                let checksum = transferred.checksums.map(|(checksum, _)| checksum);
+                if let Some(checksum) = checksum {
+                    outcome.checksums.push((destination.clone(), checksum));
+                }
+                journal.record(&destination, checksum);
             }
 
-            files_in_chunk
+            outcome
         });
         task_handles.push(handle);
     }
 
-    println!("Restructuring...");
-    let mut files_copied = 0;
-
-    // Progress bar for better user experience
-    let pb = ProgressBar::new(files_amount as u64);
-    pb.set_style(
-        ProgressStyle::with_template("[{elapsed_precise}] [{wide_bar:.blue}] ({eta})")
-            .unwrap()
-            .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
-                write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
-            })
-            .progress_chars("#>-"),
-    );
+    // Tune the amount of active tasks by the throughput, until all of the files are transferred
+    let tuner_handle = concurrency.is_adaptive().then(|| {
+        let concurrency = Arc::clone(&concurrency);
+        tokio::spawn(async move {
+            let mut tuner = Tuner::default();
+            loop {
+                tokio::time::sleep(ADJUSTMENT_INTERVAL).await;
+                concurrency.adjust(&mut tuner, ADJUSTMENT_INTERVAL);
+            }
+        })
+    });
 
+    let mut outcome = TransfersOutcome::default();
     for handle in task_handles {
-        let files_added = handle.await.unwrap();
-
-        while files_copied < files_amount {
-            files_copied += files_added;
-            // Sleeping for better user experience while rendering the progress bar for small
-            // directories. It is beningn, since won't block any thread
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-        }
+        outcome.merge(handle.await.unwrap());
+    }
+    if let Some(tuner_handle) = tuner_handle {
+        tuner_handle.abort();
     }
+    progress.finish();
 
-    println!("Restructured into '{}'", root_path.to_string_lossy());
+    log::info!("Restructured into '{}'", root_path.to_string_lossy());
+    outcome
+}
+
+/// What has happened to the files, which have been transferred by [`copy_files_in_tasks`].
+#[derive(Default)]
+struct TransfersOutcome {
+    /// Amount of the files on the file system of the destination, which have been copied instead of being transferred
+    /// in the mode
+    copied_instead: usize,
+    /// Destinations of the verified files along with checksums of their sources
+    checksums: Vec<(PathBuf, Checksum)>,
+    /// Destinations of the verified files, which don't match their sources
+    mismatched: Vec<PathBuf>,
+    /// Sources of the files, which couldn't be transferred, along with the errors
+    failed: Vec<(PathBuf, String)>,
+}
+
+impl TransfersOutcome {
+    fn merge(&mut self, other: Self) {
+        self.copied_instead += other.copied_instead;
+        self.checksums.extend(other.checksums);
+        self.mismatched.extend(other.mismatched);
+        self.failed.extend(other.failed);
+    }
 }
 
 /// For a given [`path`], traverse the directory in parallel threads and scaffold
 /// a `catalog-like` structure made of valid .DICOM files, based on the IDs of patients.
+/// Files of each patient are grouped into the `Study -> Series -> Instance` hierarchy.
 fn scaffold_catalog(
     path: PathBuf,
-    patients_id: Option<Vec<OsString>>,
-) -> CliResult<HashMap<Person, SortedPaths>> {
+    selection: &Selection,
+    use_index: bool,
+    report: &Report,
+) -> CliResult<HashMap<Person, Patient>> {
+    // Only patient's, hierarchy and selection attributes are needed, so there's no need to read the rest of the file
+    let header_reader = HeaderReader::new(
+        PATIENT_TAGS
+            .into_iter()
+            .chain(HIERARCHY_TAGS)
+            .chain(selection.tags()),
+    );
+
+    let v: Vec<(Person, Instance)> =
+        traverse_in_parallel(path, selection, use_index, &header_reader, report)?
+            .into_iter()
+            .map(|(path, attributes)| {
+                let instance = Instance::from_attributes(path, &attributes);
+                (person(&attributes), instance)
+            })
+            .collect();
+
+    // Merge results obtained from parallel threads
+    let map: HashMap<Person, Vec<Instance>> =
+        v.into_iter()
+            .fold(HashMap::new(), |mut acc, (person, instance)| {
+                acc.entry(person).or_default().push(instance);
+                acc
+            });
+
+    // Build the hierarchy with paths sorted in topological order for each patient
+    let map_with_hierarchy = map
+        .into_iter()
+        .par_bridge()
+        .map(|(person, instances)| (person, Patient::new(instances)))
+        .collect();
+
+    Ok(map_with_hierarchy)
+}
+
+/// For a given [`path`], traverse the directory in parallel threads and read attributes of each valid .DICOM file
+/// of the selected patients via [`header_reader`]. Files without a usable patient's ID are identified via the [`selection`].
+fn traverse_in_parallel(
+    path: PathBuf,
+    selection: &Selection,
+    use_index: bool,
+    header_reader: &HeaderReader,
+    report: &Report,
+) -> CliResult<Vec<(PathBuf, Attributes)>> {
     if !path.is_dir() {
         return Err(CliError::NotADirectory(path));
     }
 
-    let patients_id = patients_id.unwrap_or_default();
-    let patients_id: HashSet<Cow<'_, str>> =
-        patients_id.iter().map(|x| x.to_string_lossy()).collect();
+    let index = if use_index {
+        Some(Index::open(&path)?)
+    } else {
+        None
+    };
+
+    let started = Instant::now();
+    let progress = Progress::scan();
 
     // <https://github.com/byron/jwalk>
-    // Iterate over directory tree in parallel and accummulates (Person, PathBuf) pairs
+    // Iterate over directory tree in parallel and accummulates (path, attributes) pairs
     // for valid .DICOM files
-    let v: Vec<(Person, PathBuf)> = jwalk::WalkDir::new(path)
+    let files: Vec<(PathBuf, Attributes)> = jwalk::WalkDir::new(&path)
+        .process_read_dir(|_depth, _path, _state, children| {
+            children.retain(|child| {
+                child
+                    .as_ref()
+                    .map_or(true, |child| child.file_name() != STATE_DIR)
+            });
+        })
         .into_iter()
         .par_bridge()
         .filter_map(|dir_entry| {
-            let Ok(dir_entry) = dir_entry else {
-                // TODO: Add warning logs here
-                return None;
+            let dir_entry = match dir_entry {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => {
+                    let path = err.path().map(Path::to_path_buf).unwrap_or_default();
+                    report.log_and_add(path, Reason::Unreadable, err);
+                    return None;
+                }
             };
 
             if dir_entry.file_type().is_file() {
                 let path = dir_entry.path();
+                progress.inc(0);
 
                 // <https://docs.rs/dicom/latest/dicom/>
-                let Ok(obj) = open_file(&path) else {
-                    // TODO: Add Logs
-                    return None;
+                let mut attributes = match read_attributes(header_reader, index.as_ref(), &path) {
+                    Ok(attributes) => attributes,
+                    Err((reason, message)) => {
+                        report.log_and_add(path, reason, message);
+                        return None;
+                    }
                 };
 
-                let id = obj.element(tags::PATIENT_ID).unwrap().to_str().unwrap(); // TODO: Remove unwrap()
-                if patients_id.is_empty() || patients_id.contains(id.as_ref()) {
+                if let Some((reason, message)) = selection.identify(&mut attributes) {
+                    report.log_and_add(&path, reason, message);
+                }
+                if selection.matches(&attributes[&tags::PATIENT_ID], &attributes) {
                     let file_name = path.as_os_str().to_string_lossy();
-                    let patient_name = obj.element(tags::PATIENT_NAME).unwrap().to_str().unwrap(); // TODO: Remove unwrap()
-
-                    let person = Person {
-                        name: patient_name.to_string().into(),
-                        id: id.to_string().into(),
-                    };
-
-                    Some((person, PathBuf::from(file_name.as_ref())))
+                    Some((PathBuf::from(file_name.as_ref()), attributes))
                 } else {
                     None
                 }
@@ -249,80 +1143,286 @@ fn scaffold_catalog(
             }
         })
         .collect();
+    progress.finish();
+    log::debug!(
+        "Scanned {} and selected {} files in {:.2?}",
+        path.display(),
+        files.len(),
+        started.elapsed()
+    );
 
-    // Merge results obtained from parallel threads
-    let map: HashMap<Person, Vec<PathBuf>> =
-        v.into_iter()
-            .fold(HashMap::new(), |mut acc, (person, file)| {
-                acc.entry(person).or_default().push(file);
-                acc
-            });
+    if let Some(index) = index {
+        index.save()?;
+    }
 
-    // Sort paths in topological order for each patient
-    let map_with_sorted_paths = map
-        .into_iter()
-        .par_bridge()
-        .map(|(person, files)| {
-            let paths = SortedPaths::new(files);
-            (person, paths)
-        })
-        .collect();
+    Ok(files)
+}
+
+/// Patient, which the file with the given [`attributes`] belongs to.
+fn person(attributes: &Attributes) -> Person {
+    let value = |tag| attributes.get(&tag).cloned().unwrap_or_default();
 
-    Ok(map_with_sorted_paths)
+    Person {
+        name: value(tags::PATIENT_NAME).into(),
+        id: value(tags::PATIENT_ID).into(),
+    }
 }
 
-fn traverse_sequentially_and_print_csv<A: AsRef<Path>>(
+/// Reads requested attributes of the file at [`path`] through the [`index`], if it's used.
+fn read_attributes(
+    header_reader: &HeaderReader,
+    index: Option<&Index>,
+    path: &Path,
+) -> Result<Attributes, (Reason, String)> {
+    match index {
+        // The index keeps only whether the file has been read, so the reason is deduced
+        Some(index) => {
+            index
+                .read(header_reader, path)
+                .ok_or_else(|| match std::fs::metadata(path) {
+                    Ok(_) => (Reason::NotDicom, "isn't a DICOM file".to_string()),
+                    Err(err) => (Reason::Unreadable, err.to_string()),
+                })
+        }
+        None => header_reader.read(path).map_err(|err| {
+            // Files, which are shorter than the DICOM preamble, are readable too
+            let reason = match &err {
+                ReadError::OpenFile { .. } => Reason::Unreadable,
+                ReadError::ReadFile { source, .. }
+                    if source.kind() != std::io::ErrorKind::UnexpectedEof =>
+                {
+                    Reason::Unreadable
+                }
+                _ => Reason::NotDicom,
+            };
+            let message = match std::error::Error::source(&err) {
+                Some(source) => format!("{err}: {source}"),
+                None => err.to_string(),
+            };
+            (reason, message)
+        }),
+    }
+}
+
+/// Writes a row with patient's name, ID, values of [`tags`] and the path for each valid DICOM file
+/// into the [`writer`] as soon as it's read. Absent tags have empty values.
+fn traverse_sequentially_and_write_csv<A, W>(
     path: A,
-    person_ids: Option<Vec<OsString>>,
+    selection: &Selection,
+    use_index: bool,
+    tags: TagList,
+    csv_options: CsvOptions,
+    writer: W,
+    report: &Report,
+) -> CliResult<()>
+where
+    A: AsRef<Path>,
+    W: std::io::Write,
+{
+    let header_reader = HeaderReader::new(
+        PATIENT_TAGS
+            .into_iter()
+            .chain(tags.tags())
+            .chain(selection.tags()),
+    );
+
+    // <https://docs.rs/csv/latest/csv/>
+    let mut csv_writer = csv::WriterBuilder::new()
+        .delimiter(csv_options.delimiter)
+        .from_writer(writer);
+
+    let mut write_headers = csv_options.headers;
+    traverse_sequentially(
+        path,
+        selection,
+        use_index,
+        &header_reader,
+        report,
+        |path, attributes| {
+            if write_headers {
+                let tag_names = tags.0.iter().map(|named_tag| named_tag.name.as_str());
+                let headers = ["Name", "ID"].into_iter().chain(tag_names).chain(["Path"]);
+                csv_writer
+                    .write_record(headers)
+                    .map_err(logging::general_error("Couldn't print the catalog"))?;
+                write_headers = false;
+            }
+
+            let value = |tag| attributes.get(&tag).map_or("", String::as_str);
+            let path = path.to_string_lossy();
+            let row = [value(tags::PATIENT_NAME), value(tags::PATIENT_ID)]
+                .into_iter()
+                .chain(tags.tags().map(value))
+                .chain([path.as_ref()]);
+            csv_writer
+                .write_record(row)
+                .map_err(logging::general_error("Couldn't print the catalog"))?;
+
+            // Rows are flushed right away, so they can be consumed while the directory is being traversed
+            csv_writer
+                .flush()
+                .map_err(logging::general_error("Couldn't print the catalog"))
+        },
+    )
+}
+
+/// Prints a JSON object with the path, patient's attributes and values of [`tags`]
+/// for each valid DICOM file as soon as it's read. Absent tags have `null` values.
+fn traverse_sequentially_and_print_ndjson<A: AsRef<Path>>(
+    path: A,
+    selection: &Selection,
+    use_index: bool,
+    tags: TagList,
+    report: &Report,
 ) -> CliResult<()> {
+    /// Single line of the output.
+    #[derive(Serialize)]
+    struct FileRecord<'a> {
+        path: Cow<'a, str>,
+        name: &'a str,
+        id: &'a str,
+        #[serde(serialize_with = "serialize_in_order")]
+        tags: Vec<(&'a str, Option<&'a str>)>,
+    }
+
+    /// Serializes values of the tags as a map, preserving the order, in which the tags were requested.
+    fn serialize_in_order<S: serde::Serializer>(
+        tags: &[(&str, Option<&str>)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(tags.iter().copied())
+    }
+
+    let header_reader = HeaderReader::new(
+        PATIENT_TAGS
+            .into_iter()
+            .chain(tags.tags())
+            .chain(selection.tags()),
+    );
+
+    let mut stdout = std::io::stdout().lock();
+    traverse_sequentially(
+        path,
+        selection,
+        use_index,
+        &header_reader,
+        report,
+        |path, attributes| {
+            let value = |tag| attributes.get(&tag).map(String::as_str);
+            let record = FileRecord {
+                path: path.to_string_lossy(),
+                name: value(tags::PATIENT_NAME).unwrap_or_default(),
+                id: value(tags::PATIENT_ID).unwrap_or_default(),
+                tags: tags
+                    .0
+                    .iter()
+                    .map(|named_tag| (named_tag.name.as_str(), value(named_tag.tag)))
+                    .collect(),
+            };
+
+            serde_json::to_writer(&mut stdout, &record)
+                .map_err(logging::general_error("Couldn't print the catalog"))?;
+            writeln!(stdout).map_err(logging::general_error("Couldn't print the catalog"))
+        },
+    )
+}
+
+/// Sequentially traverses the directory and calls [`on_file`] with attributes, read by [`header_reader`],
+/// for each valid DICOM file of the requested persons. Fails, when there are no such files.
+fn traverse_sequentially<A, F>(
+    path: A,
+    selection: &Selection,
+    use_index: bool,
+    header_reader: &HeaderReader,
+    report: &Report,
+    mut on_file: F,
+) -> CliResult<()>
+where
+    A: AsRef<Path>,
+    F: FnMut(&Path, &Attributes) -> CliResult<()>,
+{
     let path = path.as_ref();
-    let walkdir = walkdir::WalkDir::new(path);
+    let started = Instant::now();
+    let walkdir = walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != STATE_DIR);
 
-    let person_ids = person_ids.unwrap_or_default();
-    let person_ids: HashSet<Cow<'_, str>> =
-        person_ids.iter().map(|x| x.to_string_lossy()).collect();
+    let index = if use_index {
+        Some(Index::open(path)?)
+    } else {
+        None
+    };
 
-    let mut print_headers = true;
+    let mut found_ids = HashSet::new();
     for entry in walkdir {
-        let Ok(entry) = entry else {
-            return Err(CliError::GeneralError);
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                let path = err.path().map(Path::to_path_buf).unwrap_or_default();
+                report.log_and_add(path, Reason::Unreadable, err);
+                continue;
+            }
         };
 
         if entry.file_type().is_file() {
             let path = entry.path();
 
-            let Ok(obj) = open_file(path) else {
-                // TODO: Log warn
-                continue;
+            let mut attributes = match read_attributes(header_reader, index.as_ref(), path) {
+                Ok(attributes) => attributes,
+                Err((reason, message)) => {
+                    report.log_and_add(path, reason, message);
+                    continue;
+                }
             };
 
-            let patient_id = obj.element(tags::PATIENT_ID).unwrap().to_str().unwrap(); // TODO: Remove unwrap()
-            if person_ids.is_empty() || person_ids.contains(patient_id.as_ref()) {
-                if print_headers {
-                    println!("Name,ID,Path");
-                    print_headers = false;
-                }
-                let patient_name = obj.element(tags::PATIENT_NAME).unwrap().to_str().unwrap(); // TODO: Remove unwrap()
-                println!("{},{},{}", patient_name, patient_id, path.to_string_lossy());
+            if let Some((reason, message)) = selection.identify(&mut attributes) {
+                report.log_and_add(path, reason, message);
+            }
+            let patient_id = &attributes[&tags::PATIENT_ID];
+            if selection.matches(patient_id, &attributes) {
+                found_ids.insert(patient_id.clone());
+                on_file(path, &attributes)?;
             }
         }
     }
 
-    if print_headers {
-        Err(CliError::FilesDoNotExist(path.into()))
-    } else {
+    if let Some(index) = index {
+        index.save()?;
+    }
+    log::debug!("Scanned {} in {:.2?}", path.display(), started.elapsed());
+
+    report_missing_ids(path, selection, &found_ids);
+
+    if !found_ids.is_empty() {
         Ok(())
+    } else {
+        Err(CliError::FilesDoNotExist(path.into()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use regex::Regex;
+    use std::ffi::OsString;
+
+    /// Flattens the hierarchy of each patient into the sorted paths of their files.
+    fn flatten(catalog: HashMap<Person, Patient>) -> HashMap<Person, SortedPaths> {
+        catalog
+            .into_iter()
+            .map(|(person, patient)| (person, patient.paths()))
+            .collect()
+    }
 
     #[test]
     fn test_scaffold_catalog() -> CliResult<()> {
         let pb = PathBuf::from("test_small_dir");
-        let scaffolded_catalog = scaffold_catalog(pb, None)?;
+        let scaffolded_catalog = flatten(scaffold_catalog(
+            pb,
+            &Selection::default(),
+            false,
+            &Report::default(),
+        )?);
 
         let mut expected: HashMap<Person, SortedPaths> = HashMap::new();
 
@@ -360,7 +1460,12 @@ mod tests {
     #[test]
     fn test_scaffold_catalog_with_ids() -> CliResult<()> {
         let pb = PathBuf::from("test_small_dir");
-        let scaffolded_catalog = scaffold_catalog(pb, Some(vec!["98.12.21".into()]))?;
+        let scaffolded_catalog = flatten(scaffold_catalog(
+            pb,
+            &Selection::new(Some(vec!["98.12.21".into()]), None),
+            false,
+            &Report::default(),
+        )?);
 
         let mut expected: HashMap<Person, SortedPaths> = HashMap::new();
 
@@ -380,4 +1485,284 @@ mod tests {
         assert_eq!(scaffolded_catalog, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_scaffold_catalog_hierarchy() -> CliResult<()> {
+        let pb = PathBuf::from("test_small_dir");
+        let scaffolded_catalog = scaffold_catalog(
+            pb,
+            &Selection::new(Some(vec!["CMB-GEC-MSB-06857".into()]), None),
+            false,
+            &Report::default(),
+        )?;
+
+        let patient = scaffolded_catalog.values().next().unwrap();
+        assert_eq!(patient.studies.len(), 1);
+        assert_eq!(patient.instance_count(), 4);
+
+        let study = patient.studies.values().next().unwrap();
+        assert_eq!(study.date, "19600527");
+        assert_eq!(study.description, "CT AbdPelvis");
+
+        let series = study.series.values().next().unwrap();
+        assert_eq!(series.number, "4");
+        assert_eq!(series.modality, "CT");
+        assert_eq!(series.description, "Sagittal");
+        assert_eq!(series.instances.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_patient_serializes_into_json() -> CliResult<()> {
+        let pb = PathBuf::from("test_small_dir");
+        let scaffolded_catalog = scaffold_catalog(
+            pb,
+            &Selection::new(Some(vec!["98.12.21".into()]), None),
+            false,
+            &Report::default(),
+        )?;
+        let (person, patient) = scaffolded_catalog.iter().next().unwrap();
+
+        let person = serde_json::to_value(person).unwrap();
+        assert_eq!(person, serde_json::json!({ "name": "", "id": "98.12.21" }));
+
+        let patient = serde_json::to_value(patient).unwrap();
+        let series = &patient["studies"][0]["series"][0];
+        assert_eq!(series["modality"], "CT");
+        assert_eq!(
+            series["instances"],
+            serde_json::json!(["test_small_dir/56364403.dcm", "test_small_dir/56364404.dcm"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_quotes_values() -> CliResult<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("a,b \"c\".dcm");
+        std::fs::copy("test_small_dir/1-010.dcm", &file_path).unwrap();
+
+        let tags = "SeriesDescription,InstitutionName".parse().unwrap();
+        let csv_options = CsvOptions {
+            delimiter: b',',
+            headers: true,
+        };
+        let mut output = Vec::new();
+        traverse_sequentially_and_write_csv(
+            dir.path(),
+            &Selection::default(),
+            false,
+            tags,
+            csv_options,
+            &mut output,
+            &Report::default(),
+        )?;
+
+        let expected = format!(
+            "Name,ID,SeriesDescription,InstitutionName,Path\n\
+             CMB-GEC-MSB-06857,CMB-GEC-MSB-06857,Sagittal,,\"{}\"\n",
+            file_path.to_string_lossy().replace('"', "\"\"")
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tsv_without_header() -> CliResult<()> {
+        let tags = "Modality".parse().unwrap();
+        let csv_options = CsvOptions {
+            delimiter: b'\t',
+            headers: false,
+        };
+        let mut output = Vec::new();
+        traverse_sequentially_and_write_csv(
+            "test_small_dir",
+            &Selection::new(Some(vec!["98.12.21".into()]), None),
+            false,
+            tags,
+            csv_options,
+            &mut output,
+            &Report::default(),
+        )?;
+
+        let mut rows: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        rows.sort();
+        assert_eq!(
+            rows,
+            [
+                "\t98.12.21\tCT\ttest_small_dir/56364403.dcm",
+                "\t98.12.21\tCT\ttest_small_dir/56364404.dcm"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_skipped_files_are_reported() -> CliResult<()> {
+        let report = Report::default();
+        scaffold_catalog(
+            PathBuf::from("test_files"),
+            &Selection::default(),
+            false,
+            &report,
+        )?;
+
+        let entries = report.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, Path::new("test_files/not_dicom.txt"));
+        assert_eq!(entries[0].reason, Reason::NotDicom);
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_without_patient_id_are_grouped() -> CliResult<()> {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, issuer) in [("1.dcm", None), ("2.dcm", Some("HOSPITAL"))] {
+            let mut obj = dicom::object::open_file("test_small_dir/56364403.dcm").unwrap();
+            obj.remove_element(tags::PATIENT_ID);
+            if let Some(issuer) = issuer {
+                obj.put(dicom::core::DataElement::new(
+                    tags::ISSUER_OF_PATIENT_ID,
+                    dicom::core::VR::LO,
+                    dicom::core::PrimitiveValue::from(issuer),
+                ));
+            }
+            obj.write_to_file(dir.path().join(name)).unwrap();
+        }
+
+        let report = Report::default();
+        let selection =
+            Selection::default().identification(Identification::new("NO-ID".to_string(), true));
+        let catalog = scaffold_catalog(dir.path().to_path_buf(), &selection, false, &report)?;
+
+        let mut ids: Vec<_> = catalog
+            .keys()
+            .map(|person| person.id.to_string_lossy())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["HOSPITAL", "NO-ID"]);
+        let reasons: Vec<_> = report.entries().iter().map(|entry| entry.reason).collect();
+        assert_eq!(
+            reasons,
+            [Reason::MissingPatientId, Reason::PatientIdFallback]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_without_patient_id_are_pseudonymized_apart() -> CliResult<()> {
+        let source = tempfile::tempdir().unwrap();
+        for (name, id) in [("1.dcm", None), ("2.dcm", Some("UNKNOWN"))] {
+            let mut obj = dicom::object::open_file("test_small_dir/56364403.dcm").unwrap();
+            obj.remove_element(tags::PATIENT_ID);
+            if let Some(id) = id {
+                obj.put(dicom::core::DataElement::new(
+                    tags::PATIENT_ID,
+                    dicom::core::VR::LO,
+                    dicom::core::PrimitiveValue::from(id),
+                ));
+            }
+            // Distinct instances, so that they don't collide
+            obj.put(dicom::core::DataElement::new(
+                tags::SOP_INSTANCE_UID,
+                dicom::core::VR::UI,
+                dicom::core::PrimitiveValue::from(format!("1.2.3.{}", &name[..1])),
+            ));
+            obj.write_to_file(source.path().join(name)).unwrap();
+        }
+        let output = tempfile::tempdir().unwrap();
+        let vault_dir = tempfile::tempdir().unwrap();
+        let vault_path = vault_dir.path().join("vault.json");
+
+        anonymize(AnonymizeOptions {
+            path: source.path().to_path_buf(),
+            output: Some(output.path().to_path_buf()),
+            retain: Vec::new(),
+            vault: Some(vault_path.clone()),
+            jobs: "1".parse().unwrap(),
+            selection: Default::default(),
+            report: None,
+            strict: false,
+        })?;
+
+        // Each file is in the directory of its own patient's ID
+        let mut patients = Vec::new();
+        for entry in walkdir::WalkDir::new(output.path()) {
+            let entry = entry.unwrap();
+            if entry.file_type().is_file() {
+                let obj = dicom::object::open_file(entry.path()).unwrap();
+                let id = obj.get(tags::PATIENT_ID).map_or(String::new(), |element| {
+                    element.to_str().unwrap().to_string()
+                });
+                let relative = entry.path().strip_prefix(output.path()).unwrap();
+                let directory = relative.iter().next().unwrap().to_string_lossy();
+                patients.push((directory.to_string(), id.trim_end_matches('\0').to_string()));
+            }
+        }
+        patients.sort();
+
+        let vault = Vault::open(&vault_path)?;
+        let pseudonym = Profile::pseudonymizing(BTreeSet::new(), vault.key()).pseudonym("UNKNOWN");
+        assert_eq!(
+            patients,
+            [
+                (pseudonym.clone(), pseudonym.clone()),
+                ("UNKNOWN".to_string(), String::new())
+            ]
+        );
+        // Only the real patient can be re-identified
+        assert_eq!(vault.reidentify(&pseudonym), Some("UNKNOWN"));
+        let ids: Vec<_> = vault
+            .mapping()
+            .filter(|(pseudonym, _)| !pseudonym.starts_with("2.25."))
+            .collect();
+        assert_eq!(ids, [(pseudonym.as_str(), "UNKNOWN")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scaffold_catalog_with_filter() -> CliResult<()> {
+        let pb = PathBuf::from("test_small_dir");
+        let filter = r#"StudyDescription ~ "lung" && SeriesNumber < 10"#.parse().unwrap();
+        let selection = Selection::new(None, Some(filter));
+        let scaffolded_catalog =
+            flatten(scaffold_catalog(pb, &selection, false, &Report::default())?);
+
+        let p1 = Person {
+            name: "".into(),
+            id: "98.12.21".into(),
+        };
+        let expected = HashMap::from([(
+            p1,
+            SortedPaths::new(vec![
+                PathBuf::from("test_small_dir/56364404.dcm"),
+                PathBuf::from("test_small_dir/56364403.dcm"),
+            ]),
+        )]);
+
+        assert_eq!(scaffolded_catalog, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scaffold_catalog_with_id_patterns() -> CliResult<()> {
+        let pb = PathBuf::from("test_small_dir");
+        let selection = Selection::new(None, None)
+            .ids_regex(vec![Regex::new(r"^cmb-gec-msb-\d+$").unwrap()])
+            .ids_glob(vec!["98.*".parse().unwrap()])
+            .ignore_case(true);
+        let scaffolded_catalog =
+            scaffold_catalog(pb.clone(), &selection, false, &Report::default())?;
+        assert_eq!(scaffolded_catalog.len(), 2);
+
+        let selection = selection.exclude_ids(vec!["cmb-gec-msb-06857".into()]);
+        let scaffolded_catalog = scaffold_catalog(pb, &selection, false, &Report::default())?;
+        let ids: Vec<&OsString> = scaffolded_catalog.keys().map(|person| &person.id).collect();
+        assert_eq!(ids, ["98.12.21"]);
+        Ok(())
+    }
 }
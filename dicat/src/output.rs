@@ -0,0 +1,234 @@
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::errors::{CliError, CliResult};
+
+/// File in the root of the restructured directory, which maps encoded names of the patients back to their IDs.
+pub const PATIENTS_FILE: &str = "patients.csv";
+
+/// File, which is briefly created in the destination to check whether it is writable.
+const WRITE_CHECK_FILE: &str = ".dicat_write_check";
+
+/// Name of a new `dicat_<timestamp>` directory in the current one. The directory itself is created by
+/// [`create_unique_root`], which adds a suffix, when the name has been taken.
+pub fn default_root() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    // Relative to the current directory, like the rest of the paths provided by the user
+    PathBuf::from(format!("dicat_{timestamp}"))
+}
+
+/// The closest of the [`path`] and its ancestors, which exists. Relative paths fall back to the current directory.
+pub fn existing_ancestor(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("."))
+        .to_path_buf()
+}
+
+/// Top-level directory of the [`root`], which contains the [`file`], i.e. the patient's directory in the default layout.
+/// Files outside of its sub-directories fall back to their own directory.
+pub fn patient_directory(root: &Path, file: &Path) -> PathBuf {
+    let parent = file.parent().unwrap_or(root);
+    match file.strip_prefix(root).map(Path::components) {
+        Ok(mut components) if components.clone().count() > 1 => match components.next() {
+            Some(Component::Normal(directory)) => root.join(directory),
+            _ => parent.to_path_buf(),
+        },
+        _ => parent.to_path_buf(),
+    }
+}
+
+/// Checks, that the [`destination`] is an existing writable directory with at least [`needed`] bytes of free space.
+pub fn check_destination(destination: &Path, needed: u64) -> CliResult<()> {
+    check_directory(destination)?;
+
+    // Permissions alone don't tell whether the directory is writable, e.g. on read-only file systems
+    let write_check = destination.join(WRITE_CHECK_FILE);
+    std::fs::File::create(&write_check)
+        .and_then(|_| std::fs::remove_file(&write_check))
+        .map_err(|_| CliError::NotWritable(destination.into()))?;
+
+    check_space(destination, needed)
+}
+
+/// Checks the [`destination`] like [`check_destination`] without writing anything into it, so that a dry run
+/// can look at it before it's touched. Missing permissions and free space are only warned about.
+pub fn preview_destination(destination: &Path, needed: u64) -> CliResult<()> {
+    check_directory(destination)?;
+
+    let read_only = std::fs::metadata(destination)
+        .map(|metadata| metadata.permissions().readonly())
+        .unwrap_or_default();
+    if read_only {
+        log::warn!("{}", CliError::NotWritable(destination.into()));
+    }
+    if let Err(err) = check_space(destination, needed) {
+        log::warn!("{err}");
+    }
+    Ok(())
+}
+
+/// Checks, that the [`destination`] is an existing directory.
+fn check_directory(destination: &Path) -> CliResult<()> {
+    if !destination.exists() {
+        return Err(CliError::DirectoryDoesNotExist(destination.into()));
+    }
+    if !destination.is_dir() {
+        return Err(CliError::NotADirectory(destination.into()));
+    }
+    Ok(())
+}
+
+/// Checks, that the file system of the [`destination`] has at least [`needed`] bytes of free space.
+fn check_space(destination: &Path, needed: u64) -> CliResult<()> {
+    let available =
+        fs2::available_space(destination).map_err(|_| CliError::NotWritable(destination.into()))?;
+    if available < needed {
+        return Err(CliError::NotEnoughSpace(
+            destination.into(),
+            needed,
+            available,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Creates a new [`root`] directory along with its missing ancestors and returns its path. When it already exists,
+/// e.g. after another run in the same second, `_1`, `_2`, ... suffixes are tried. Creation is atomic,
+/// so concurrent runs never share a directory.
+pub fn create_unique_root(root: &Path) -> CliResult<PathBuf> {
+    let parent = root.parent().unwrap_or(Path::new(""));
+    if !parent.as_os_str().is_empty() {
+        std::fs::create_dir_all(parent)
+            .map_err(|_| CliError::CreatingDirectoryError(parent.into()))?;
+    }
+    let name = root.file_name().unwrap_or_default().to_string_lossy();
+
+    for attempt in 0.. {
+        let path = match attempt {
+            0 => root.to_path_buf(),
+            _ => parent.join(format!("{name}_{attempt}")),
+        };
+
+        match std::fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(_) => return Err(CliError::CreatingDirectoryError(path)),
+        }
+    }
+
+    unreachable!("suffixes aren't exhausted before the directory is created")
+}
+
+/// Writes the [`patients`] mapping into [`PATIENTS_FILE`] of the [`root`], keeping the patients,
+/// which have been restructured into it before.
+pub fn write_patients(root: &Path, patients: &BTreeMap<String, String>) -> CliResult<()> {
+    let path = root.join(PATIENTS_FILE);
+    let error = |_| CliError::PatientsError(path.clone());
+
+    let mut entries = BTreeMap::new();
+    if path.exists() {
+        let mut reader = csv::Reader::from_path(&path).map_err(error)?;
+        for record in reader.deserialize() {
+            let (name, id): (String, String) = record.map_err(error)?;
+            entries.insert(name, id);
+        }
+    }
+    entries.extend(patients.clone());
+
+    let mut writer = csv::Writer::from_path(&path).map_err(error)?;
+    writer
+        .write_record(["Directory", "PatientID"])
+        .map_err(error)?;
+    for entry in &entries {
+        writer.serialize(entry).map_err(error)?;
+    }
+    writer
+        .flush()
+        .map_err(|_| CliError::PatientsError(path.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_unique_root() -> CliResult<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("nested/dicat_1700000000");
+
+        // Directories, which already exist, are never shared
+        let first = create_unique_root(&root)?;
+        let second = create_unique_root(&root)?;
+        let third = create_unique_root(&root)?;
+
+        assert_eq!(first, root);
+        assert_eq!(second, dir.path().join("nested/dicat_1700000000_1"));
+        assert_eq!(third, dir.path().join("nested/dicat_1700000000_2"));
+        assert!(third.is_dir());
+
+        assert_eq!(
+            existing_ancestor(&dir.path().join("missing/98.12.21")),
+            dir.path()
+        );
+        assert_eq!(
+            existing_ancestor(Path::new("dicat_1700000000")),
+            Path::new(".")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_patients() -> CliResult<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let encoded = |id: &str| (crate::layout::encode(id), id.to_string());
+
+        write_patients(dir.path(), &[encoded("../../etc"), encoded("a,b")].into())?;
+        write_patients(dir.path(), &[encoded("98.12.21")].into())?;
+
+        let content = std::fs::read_to_string(dir.path().join(PATIENTS_FILE)).unwrap();
+        assert_eq!(
+            content,
+            "Directory,PatientID\n..%2F..%2Fetc,../../etc\n98.12.21,98.12.21\n\"a,b\",\"a,b\"\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_destination(dir.path(), 0).is_ok());
+        assert!(!dir.path().join(WRITE_CHECK_FILE).exists());
+
+        assert!(matches!(
+            check_destination(dir.path(), u64::MAX),
+            Err(CliError::NotEnoughSpace(..))
+        ));
+        assert!(matches!(
+            check_destination(&dir.path().join("missing"), 0),
+            Err(CliError::DirectoryDoesNotExist(_))
+        ));
+        // Dry runs only warn about the problems, which would fail the transfers
+        assert!(preview_destination(dir.path(), u64::MAX).is_ok());
+        assert!(matches!(
+            preview_destination(&dir.path().join("missing"), 0),
+            Err(CliError::DirectoryDoesNotExist(_))
+        ));
+
+        let file = dir.path().join("file.dcm");
+        std::fs::write(&file, "").unwrap();
+        assert!(matches!(
+            check_destination(&file, 0),
+            Err(CliError::NotADirectory(_))
+        ));
+    }
+}
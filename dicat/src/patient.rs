@@ -0,0 +1,180 @@
+use dicom::{core::Tag, dictionary_std::tags};
+
+use crate::{
+    header::{Attributes, NamedTag},
+    prompt_parser::options::IdentificationArgs,
+    report::Reason,
+};
+
+/// ID of the patient, which files without a usable `PatientID` are grouped under by default.
+pub const DEFAULT_UNKNOWN_PATIENT: &str = "UNKNOWN";
+
+/// `OtherPatientIDs (0010,1000)` has been retired from the standard, but it's still present in older files.
+pub const OTHER_PATIENT_IDS: Tag = Tag(0x0010, 0x1000);
+
+/// Tags, which identify the patient, when `PatientID` is unusable, in the order they are tried.
+pub const FALLBACK_TAGS: [Tag; 2] = [OTHER_PATIENT_IDS, tags::ISSUER_OF_PATIENT_ID];
+
+/// Decides, which patient a DICOM file belongs to, when its `PatientID` is absent, empty or can't be decoded.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::{
+///     patient::{Identification, OTHER_PATIENT_IDS},
+///     report::Reason,
+/// };
+/// use dicom::dictionary_std::tags;
+///
+/// let identification = Identification::new("NO-ID".into(), true);
+///
+/// let mut attributes = [(OTHER_PATIENT_IDS, "MRN-17\\MRN-18".to_string())].into();
+/// let (reason, _) = identification.identify(&mut attributes).unwrap();
+/// assert_eq!(reason, Reason::PatientIdFallback);
+/// assert_eq!(attributes[&tags::PATIENT_ID], "MRN-17");
+///
+/// let mut attributes = Default::default();
+/// let (reason, _) = identification.identify(&mut attributes).unwrap();
+/// assert_eq!(reason, Reason::MissingPatientId);
+/// assert_eq!(attributes[&tags::PATIENT_ID], "NO-ID");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identification {
+    /// ID of the patient, which files without a usable `PatientID` are grouped under, unless they are left unidentified
+    unknown: Option<String>,
+    fallback: bool,
+}
+
+impl Identification {
+    /// Creates an identification, which groups files without `PatientID` under the [`unknown`] patient.
+    /// When [`fallback`] is set, [`FALLBACK_TAGS`] are tried first.
+    pub fn new(unknown: String, fallback: bool) -> Self {
+        Self {
+            unknown: Some(unknown),
+            fallback,
+        }
+    }
+
+    /// Creates an identification, which leaves files without a usable `PatientID` with an empty one,
+    /// e.g. when they are de-identified, so that they aren't mistaken for a patient with the substituted ID.
+    pub fn unidentified() -> Self {
+        Self {
+            unknown: None,
+            fallback: false,
+        }
+    }
+
+    /// Tags, besides `PatientID`, which are needed to identify the patient.
+    pub fn tags(&self) -> Vec<Tag> {
+        if self.fallback {
+            FALLBACK_TAGS.to_vec()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Makes sure, that `PatientID` of the [`attributes`] is usable, replacing it otherwise.
+    /// Returns the reason and the description of the replacement, when it has been made.
+    pub fn identify(&self, attributes: &mut Attributes) -> Option<(Reason, String)> {
+        if attributes
+            .get(&tags::PATIENT_ID)
+            .is_some_and(|id| !id.is_empty())
+        {
+            return None;
+        }
+
+        let fallback = FALLBACK_TAGS
+            .into_iter()
+            .filter(|_| self.fallback)
+            .find_map(|tag| Some((tag, first_value(attributes.get(&tag)?)?)));
+        let (id, reason, message) = match (fallback, &self.unknown) {
+            (Some((tag, id)), _) => (
+                id.to_string(),
+                Reason::PatientIdFallback,
+                format!(
+                    "PatientID is unusable, {} `{id}` is used instead",
+                    NamedTag::from(tag).name
+                ),
+            ),
+            (None, Some(unknown)) => (
+                unknown.clone(),
+                Reason::MissingPatientId,
+                format!("PatientID is unusable, grouped under `{unknown}`"),
+            ),
+            (None, None) => {
+                attributes.insert(tags::PATIENT_ID, String::new());
+                return None;
+            }
+        };
+        attributes.insert(tags::PATIENT_ID, id);
+
+        Some((reason, message))
+    }
+}
+
+impl IdentificationArgs {
+    /// Identification of the patients according to the `--unknown-patient` and `--patient-id-fallback` options.
+    pub fn identification(&self) -> Identification {
+        Identification::new(self.unknown_patient.clone(), self.patient_id_fallback)
+    }
+}
+
+impl Default for Identification {
+    fn default() -> Self {
+        Self::new(DEFAULT_UNKNOWN_PATIENT.to_string(), false)
+    }
+}
+
+/// The first non-empty value of a multi-valued attribute, which values are separated by `\`.
+fn first_value(value: &str) -> Option<&str> {
+    value
+        .split('\\')
+        .map(str::trim)
+        .find(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify() {
+        let identification = Identification::default();
+
+        let mut attributes: Attributes = [(tags::PATIENT_ID, "98.12.21".to_string())].into();
+        assert_eq!(identification.identify(&mut attributes), None);
+        assert_eq!(attributes[&tags::PATIENT_ID], "98.12.21");
+
+        // Fallback tags aren't used, unless they are enabled
+        let mut attributes: Attributes = [
+            (tags::PATIENT_ID, String::new()),
+            (tags::ISSUER_OF_PATIENT_ID, "HOSPITAL".to_string()),
+        ]
+        .into();
+        let (reason, _) = identification.identify(&mut attributes).unwrap();
+        assert_eq!(reason, Reason::MissingPatientId);
+        assert_eq!(attributes[&tags::PATIENT_ID], DEFAULT_UNKNOWN_PATIENT);
+
+        // Empty values of the fallback tags are skipped
+        let identification = Identification::new(DEFAULT_UNKNOWN_PATIENT.to_string(), true);
+        let mut attributes: Attributes = [
+            (OTHER_PATIENT_IDS, " \\ ".to_string()),
+            (tags::ISSUER_OF_PATIENT_ID, "HOSPITAL".to_string()),
+        ]
+        .into();
+        let (reason, message) = identification.identify(&mut attributes).unwrap();
+        assert_eq!(reason, Reason::PatientIdFallback);
+        assert_eq!(
+            message,
+            "PatientID is unusable, IssuerOfPatientID `HOSPITAL` is used instead"
+        );
+        assert_eq!(attributes[&tags::PATIENT_ID], "HOSPITAL");
+
+        // Unidentified files keep an empty ID
+        let mut attributes = Attributes::new();
+        assert_eq!(
+            Identification::unidentified().identify(&mut attributes),
+            None
+        );
+        assert_eq!(attributes[&tags::PATIENT_ID], "");
+    }
+}
@@ -0,0 +1,643 @@
+use dicom::dictionary_std::tags;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::{CliError, CliResult},
+    header::Attributes,
+    layout::{self, Layout},
+    prompt_parser::options::CollisionPolicy,
+};
+
+/// A single file, which is restructured from its [`Self::source`] to its [`Self::destination`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Transfer {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// Size of the source, when the plan has been made
+    #[serde(default)]
+    pub size: u64,
+}
+
+impl Transfer {
+    /// Transfer of the file at [`source`] to the [`destination`], which reads the size of the source right away.
+    pub fn new(source: PathBuf, destination: PathBuf) -> Self {
+        let size = std::fs::metadata(&source).map_or(0, |metadata| metadata.len());
+        Self {
+            source,
+            destination,
+            size,
+        }
+    }
+}
+
+/// A file, which destination is already taken by [`Self::other`] file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collision {
+    pub source: PathBuf,
+    pub other: PathBuf,
+    pub destination: PathBuf,
+    pub resolution: Resolution,
+}
+
+/// The way a [`Collision`] has been resolved according to the [`CollisionPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    Renamed(PathBuf),
+    Skipped,
+    Failed,
+}
+
+/// Destinations of all of the restructured files, which are decided before anything is copied.
+/// It can be saved as JSON and executed later without traversing the original directory again.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::{layout::Layout, plan::Plan};
+/// use dicom::dictionary_std::tags;
+/// use std::path::{Path, PathBuf};
+///
+/// let attributes = [(tags::PATIENT_ID, "98.12.21".to_string())].into();
+/// let files = vec![
+///     (PathBuf::from("a/1.dcm"), attributes),
+///     (PathBuf::from("b/1.dcm"), Default::default()),
+/// ];
+///
+/// let plan = Plan::new(&files, Path::new("out"), &Layout::default(), Default::default());
+/// assert_eq!(plan.transfers[0].destination, PathBuf::from("out/98.12.21/1.dcm"));
+/// assert_eq!(plan.transfers[1].destination, PathBuf::from("out/UNKNOWN/1.dcm"));
+/// assert_eq!(plan.directories.len(), 3);
+/// assert!(plan.collisions.is_empty());
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    /// Directory, which contains all of the destinations
+    pub root: PathBuf,
+    /// Total size of the transferred files, i.e. of their sources
+    pub bytes: u64,
+    /// Directories, which don't exist yet and have to be created, including the [`Self::root`]
+    pub directories: BTreeSet<PathBuf>,
+    pub transfers: Vec<Transfer>,
+    pub collisions: Vec<Collision>,
+    /// Original IDs of the patients by their top-level directories, when the layout starts with `{PatientID}`
+    #[serde(default)]
+    pub patients: BTreeMap<String, String>,
+    /// Whether the [`Self::root`] is a new directory, which is created by the run, rather than an existing one.
+    /// A new one is never shared with another run, even when its name has been taken
+    #[serde(default)]
+    pub new_root: bool,
+}
+
+impl Plan {
+    /// Places each of the [`files`] inside of the [`root`] according to the [`layout`]. Files, which end up
+    /// at the same destination as the previous ones or as the files in the [`root`], are handled according to the [`policy`].
+    pub fn new(
+        files: &[(PathBuf, Attributes)],
+        root: &Path,
+        layout: &Layout,
+        policy: CollisionPolicy,
+    ) -> Self {
+        let mut files: Vec<(&PathBuf, &Attributes)> = files
+            .iter()
+            .map(|(path, attributes)| (path, attributes))
+            .collect();
+        files.sort();
+
+        let mut plan = Self {
+            root: root.to_path_buf(),
+            ..Default::default()
+        };
+        // Destinations, which are taken, along with the files, which take them
+        let mut taken: HashMap<PathBuf, PathBuf> = HashMap::new();
+        let other_file = |taken: &HashMap<PathBuf, PathBuf>, destination: &PathBuf| match taken
+            .get(destination)
+        {
+            Some(source) => Some(source.clone()),
+            None => destination.exists().then(|| destination.clone()),
+        };
+
+        for (source, attributes) in files {
+            let rendered = layout.render(source, attributes);
+            // Top-level directories refer to the patients only in the layouts, which start with their IDs
+            if let Some(id) = attributes
+                .get(&tags::PATIENT_ID)
+                .filter(|id| !id.is_empty() && layout.starts_with_patient_id())
+            {
+                if let Some(directory) = rendered.iter().next() {
+                    let directory = directory.to_string_lossy().into_owned();
+                    plan.patients.insert(directory, id.clone());
+                }
+            }
+            let destination = root.join(rendered);
+
+            let Some(other) = other_file(&taken, &destination) else {
+                taken.insert(destination.clone(), source.clone());
+                plan.transfers
+                    .push(Transfer::new(source.clone(), destination));
+                continue;
+            };
+
+            let is_free = |path: &PathBuf| other_file(&taken, path).is_none();
+            let renamed = match policy {
+                CollisionPolicy::Fail => None,
+                CollisionPolicy::SkipIdentical if same_content(source, &other) => None,
+                CollisionPolicy::Suffix | CollisionPolicy::SkipIdentical => {
+                    Some(with_suffix(&destination, is_free))
+                }
+                CollisionPolicy::SopUid => {
+                    let renamed = attributes
+                        .get(&tags::SOP_INSTANCE_UID)
+                        .filter(|uid| !uid.is_empty())
+                        // The UID comes from the file, so it mustn't be able to escape the directory
+                        .map(|uid| {
+                            destination.with_file_name(format!("{}.dcm", layout::encode(uid)))
+                        })
+                        .filter(is_free);
+                    Some(renamed.unwrap_or_else(|| with_suffix(&destination, is_free)))
+                }
+            };
+
+            let resolution = match renamed {
+                Some(renamed) => {
+                    taken.insert(renamed.clone(), source.clone());
+                    plan.transfers
+                        .push(Transfer::new(source.clone(), renamed.clone()));
+                    Resolution::Renamed(renamed)
+                }
+                None if policy == CollisionPolicy::Fail => Resolution::Failed,
+                None => Resolution::Skipped,
+            };
+            plan.collisions.push(Collision {
+                source: source.clone(),
+                other,
+                destination,
+                resolution,
+            });
+        }
+
+        for transfer in &plan.transfers {
+            plan.bytes += transfer.size;
+
+            let missing = transfer
+                .destination
+                .ancestors()
+                .skip(1)
+                .take_while(|directory| !directory.as_os_str().is_empty() && !directory.exists());
+            plan.directories.extend(missing.map(Path::to_path_buf));
+        }
+
+        plan
+    }
+
+    /// Makes all of the paths absolute, so that the plan doesn't depend on the current directory.
+    pub fn absolute(self) -> Self {
+        self.map_paths(|path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+    }
+
+    /// Moves the destinations from the current root into the new [`root`], e.g. when the name of the current one
+    /// has been taken by another run in the meantime.
+    pub fn rebase(self, root: &Path) -> Self {
+        let old_root = self.root.clone();
+        self.map_paths(|path| match path.strip_prefix(&old_root) {
+            Ok(relative) if relative.as_os_str().is_empty() => root.to_path_buf(),
+            Ok(relative) => root.join(relative),
+            Err(_) => path.to_path_buf(),
+        })
+    }
+
+    /// Replaces each of the paths of the plan with the result of the [`map`].
+    fn map_paths(mut self, map: impl Fn(&Path) -> PathBuf) -> Self {
+        let map_path = |path: &mut PathBuf| *path = map(path);
+
+        map_path(&mut self.root);
+        self.directories = self.directories.iter().map(|path| map(path)).collect();
+        for transfer in &mut self.transfers {
+            map_path(&mut transfer.source);
+            map_path(&mut transfer.destination);
+        }
+        for collision in &mut self.collisions {
+            map_path(&mut collision.source);
+            map_path(&mut collision.other);
+            map_path(&mut collision.destination);
+            if let Resolution::Renamed(renamed) = &mut collision.resolution {
+                map_path(renamed);
+            }
+        }
+        self
+    }
+
+    /// Reads the plan, which has been saved as JSON, from the file at [`path`].
+    pub fn read(path: &Path) -> CliResult<Self> {
+        let file = File::open(path).map_err(|_| CliError::PlanError(path.into()))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|_| CliError::PlanError(path.into()))
+    }
+
+    /// Checks whether any of the collisions couldn't be resolved.
+    pub fn has_failed(&self) -> bool {
+        self.collisions
+            .iter()
+            .any(|collision| collision.resolution == Resolution::Failed)
+    }
+
+    /// Fails the transfers, which destinations have been taken since the plan was made, so that a saved plan
+    /// never replaces any file, when it's executed. A new root is created anew, so its destinations are free.
+    pub fn recheck(&mut self) {
+        if self.new_root {
+            return;
+        }
+
+        let taken: Vec<_> = self
+            .transfers
+            .iter()
+            .filter(|transfer| std::fs::symlink_metadata(&transfer.destination).is_ok())
+            .map(|transfer| Collision {
+                source: transfer.source.clone(),
+                other: transfer.destination.clone(),
+                destination: transfer.destination.clone(),
+                resolution: Resolution::Failed,
+            })
+            .collect();
+        self.collisions.extend(taken);
+    }
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.other == self.destination {
+            write!(
+                f,
+                "{} collides with the existing file {}",
+                self.source.display(),
+                self.destination.display()
+            )?;
+        } else {
+            write!(
+                f,
+                "{} collides with {} at {}",
+                self.source.display(),
+                self.other.display(),
+                self.destination.display()
+            )?;
+        }
+
+        match &self.resolution {
+            Resolution::Renamed(renamed) => write!(f, ", so it's renamed to {}", renamed.display()),
+            Resolution::Skipped => write!(f, ", but they are identical, so it's skipped"),
+            Resolution::Failed => Ok(()),
+        }
+    }
+}
+
+/// The first of `name_1.ext`, `name_2.ext`, ... paths, which [`is_free`].
+fn with_suffix<F: Fn(&PathBuf) -> bool>(path: &Path, is_free: F) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|i| path.with_file_name(format!("{stem}_{i}{extension}")))
+        .find(is_free)
+        .unwrap()
+}
+
+/// Checks whether files at [`a`] and [`b`] have the same content. Files, which can't be read, are different.
+fn same_content(a: &Path, b: &Path) -> bool {
+    let (Ok(a), Ok(b)) = (File::open(a), File::open(b)) else {
+        return false;
+    };
+    let same_size = match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.len() == b.len(),
+        _ => false,
+    };
+    if !same_size {
+        return false;
+    }
+
+    let (mut a, mut b) = (a, b);
+    let (mut a_buffer, mut b_buffer) = ([0; 8192], [0; 8192]);
+    loop {
+        match (fill(&mut a, &mut a_buffer), fill(&mut b, &mut b_buffer)) {
+            (Ok(0), Ok(0)) => return true,
+            (Ok(a_len), Ok(b_len)) if a_buffer[..a_len] == b_buffer[..b_len] => continue,
+            _ => return false,
+        }
+    }
+}
+
+/// Reads from the [`reader`] until the [`buffer`] is full or the end is reached.
+fn fill<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOPOGRAM: &str = "test_files/1.000000-Topogram  0.6  T20f-64071zxczxczxczxcsdfsdfs";
+
+    /// Identical files, which have the same name in different sub-directories, along with a missing one
+    /// with the same name, which is never identical, and a file with another name.
+    fn files() -> Vec<(PathBuf, Attributes)> {
+        let attributes = |uid: &str| {
+            [
+                (tags::PATIENT_ID, "98.12.21".to_string()),
+                (tags::SOP_INSTANCE_UID, uid.to_string()),
+            ]
+            .into()
+        };
+
+        vec![
+            (
+                Path::new(TOPOGRAM).join("Pacieto/56364401.dcm"),
+                attributes("1.2.3"),
+            ),
+            (
+                Path::new(TOPOGRAM).join("56364401.dcm"),
+                attributes("1.2.3"),
+            ),
+            (
+                Path::new(TOPOGRAM).join("Pacieto/Pacieto2/56364401.dcm"),
+                attributes(""),
+            ),
+            (
+                PathBuf::from("test_files/Inner/56364402.dcm"),
+                attributes("1.2.4"),
+            ),
+            (
+                PathBuf::from("test_files/56364401.dcm"),
+                attributes("1.2.5"),
+            ),
+        ]
+    }
+
+    fn plan(policy: CollisionPolicy) -> Plan {
+        Plan::new(&files(), Path::new("out"), &Layout::default(), policy)
+    }
+
+    fn destinations(plan: &Plan) -> Vec<&str> {
+        plan.transfers
+            .iter()
+            .map(|transfer| transfer.destination.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_with_suffixes() {
+        let plan = plan(CollisionPolicy::Suffix);
+        assert_eq!(
+            destinations(&plan),
+            [
+                "out/98.12.21/56364401.dcm",
+                "out/98.12.21/56364401_1.dcm",
+                "out/98.12.21/56364401_2.dcm",
+                "out/98.12.21/56364401_3.dcm",
+                "out/98.12.21/56364402.dcm",
+            ]
+        );
+        assert_eq!(plan.collisions.len(), 3);
+        assert_eq!(
+            plan.collisions[0].other,
+            Path::new(TOPOGRAM).join("56364401.dcm")
+        );
+        assert!(!plan.has_failed());
+    }
+
+    #[test]
+    fn test_plan_with_sop_uids() {
+        let plan = plan(CollisionPolicy::SopUid);
+        assert_eq!(
+            destinations(&plan),
+            [
+                "out/98.12.21/56364401.dcm",
+                "out/98.12.21/1.2.3.dcm",
+                "out/98.12.21/56364401_1.dcm",
+                "out/98.12.21/1.2.5.dcm",
+                "out/98.12.21/56364402.dcm",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_encodes_sop_uids() {
+        let mut files = files();
+        files.truncate(1);
+        // Files are planned in order of their sources and then attributes, so the first one keeps its name
+        files[0].1.insert(tags::SOP_INSTANCE_UID, String::new());
+        for uid in ["../../x/y", ".."] {
+            let mut attributes = files[0].1.clone();
+            attributes.insert(tags::SOP_INSTANCE_UID, uid.to_string());
+            files.push((files[0].0.clone(), attributes));
+        }
+
+        let plan = Plan::new(
+            &files,
+            Path::new("out"),
+            &Layout::default(),
+            CollisionPolicy::SopUid,
+        );
+        assert_eq!(
+            destinations(&plan),
+            [
+                "out/98.12.21/56364401.dcm",
+                "out/98.12.21/%2E%2E.dcm",
+                "out/98.12.21/..%2F..%2Fx%2Fy.dcm",
+            ]
+        );
+        assert!(plan
+            .transfers
+            .iter()
+            .all(|transfer| transfer.destination.parent() == Some(Path::new("out/98.12.21"))));
+    }
+
+    #[test]
+    fn test_plan_skips_identical_files() {
+        let plan = plan(CollisionPolicy::SkipIdentical);
+        assert_eq!(
+            destinations(&plan),
+            [
+                "out/98.12.21/56364401.dcm",
+                "out/98.12.21/56364401_1.dcm",
+                "out/98.12.21/56364402.dcm",
+            ]
+        );
+        let resolutions: Vec<&Resolution> = plan
+            .collisions
+            .iter()
+            .map(|collision| &collision.resolution)
+            .collect();
+        assert_eq!(
+            resolutions,
+            [
+                &Resolution::Skipped,
+                &Resolution::Skipped,
+                &Resolution::Renamed(PathBuf::from("out/98.12.21/56364401_1.dcm")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_fails_on_collisions() {
+        let plan = plan(CollisionPolicy::Fail);
+        assert_eq!(plan.transfers.len(), 2);
+        assert_eq!(plan.collisions.len(), 3);
+        assert!(plan.has_failed());
+        // The total consists of the sizes of the sources, which the progress is advanced by
+        let size = |transfer: &Transfer| std::fs::metadata(&transfer.source).unwrap().len();
+        assert!(plan
+            .transfers
+            .iter()
+            .all(|transfer| transfer.size == size(transfer)));
+        assert_eq!(plan.bytes, plan.transfers.iter().map(size).sum::<u64>());
+    }
+
+    #[test]
+    fn test_plan_collides_with_existing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("98.12.21/56364402.dcm");
+        std::fs::create_dir(existing.parent().unwrap()).unwrap();
+        std::fs::copy("test_files/Inner/56364402.dcm", &existing).unwrap();
+
+        let files = &files()[3..4];
+        let plan = Plan::new(
+            files,
+            dir.path(),
+            &Layout::default(),
+            CollisionPolicy::SkipIdentical,
+        );
+        assert!(plan.transfers.is_empty());
+        assert_eq!(plan.collisions[0].other, existing);
+    }
+
+    #[test]
+    fn test_plan_can_be_saved_and_read() -> CliResult<()> {
+        let plan = plan(CollisionPolicy::SkipIdentical);
+        assert_eq!(plan.root, PathBuf::from("out"));
+        assert_eq!(
+            plan.directories,
+            [PathBuf::from("out"), PathBuf::from("out/98.12.21")].into()
+        );
+        // The missing file doesn't take any space
+        let size = |path: PathBuf| std::fs::metadata(path).unwrap().len();
+        assert_eq!(
+            plan.bytes,
+            size(Path::new(TOPOGRAM).join("56364401.dcm"))
+                + size(PathBuf::from("test_files/Inner/56364402.dcm"))
+        );
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        serde_json::to_writer(&mut file, &plan).unwrap();
+        assert_eq!(Plan::read(file.path())?, plan);
+        assert!(Plan::read(Path::new("test_files/not_dicom.txt")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_fails_on_destinations_taken_since_it_was_made() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan = || {
+            Plan::new(
+                &files(),
+                dir.path(),
+                &Layout::default(),
+                CollisionPolicy::Suffix,
+            )
+        };
+        let (mut plan, mut new_root) = (plan(), plan());
+        let taken = plan.transfers[1].destination.clone();
+        std::fs::create_dir_all(taken.parent().unwrap()).unwrap();
+        std::fs::write(&taken, "").unwrap();
+
+        new_root.new_root = true;
+        new_root.recheck();
+        assert!(!new_root.has_failed());
+
+        plan.recheck();
+        assert!(plan.has_failed());
+        let collision = plan.collisions.last().unwrap();
+        assert_eq!(collision.destination, taken);
+        assert_eq!(collision.resolution, Resolution::Failed);
+    }
+
+    #[test]
+    fn test_plan_maps_patients_of_the_top_level_directories() {
+        let files = [
+            (
+                PathBuf::from("a/1.dcm"),
+                [
+                    (tags::PATIENT_ID, "Doe/John".to_string()),
+                    (tags::STUDY_DATE, "20230512".to_string()),
+                ]
+                .into(),
+            ),
+            (PathBuf::from("b/1.dcm"), Attributes::new()),
+        ];
+        let plan = |layout: &str| {
+            let layout = layout.parse().unwrap();
+            Plan::new(&files, Path::new("out"), &layout, Default::default())
+        };
+
+        assert_eq!(
+            plan("{PatientID}/{StudyDate}/{FileName}").patients,
+            [("Doe%2FJohn".to_string(), "Doe/John".to_string())].into()
+        );
+        // The directories don't refer to the patients in the other layouts
+        for layout in [
+            "{StudyDate}/{PatientID}/{FileName}",
+            "{PatientID:lower}/{FileName}",
+            "{PatientID|StudyDate}/{FileName}",
+            "id_{PatientID}/{FileName}",
+        ] {
+            assert!(plan(layout).patients.is_empty(), "{layout}");
+        }
+    }
+
+    #[test]
+    fn test_plan_can_be_rebased_and_made_absolute() {
+        let plan = plan(CollisionPolicy::Suffix);
+        assert_eq!(plan.root.to_str(), Some("out"));
+        let plan = plan.rebase(Path::new("out_1"));
+        assert_eq!(plan.root.to_str(), Some("out_1"));
+        assert_eq!(
+            plan.directories,
+            [PathBuf::from("out_1"), PathBuf::from("out_1/98.12.21")].into()
+        );
+        assert!(plan
+            .transfers
+            .iter()
+            .all(|transfer| transfer.destination.starts_with("out_1")
+                && transfer.source.starts_with("test_files")));
+        assert!(plan.collisions.iter().all(|collision| {
+            collision.destination.starts_with("out_1")
+                && matches!(&collision.resolution, Resolution::Renamed(renamed) if renamed.starts_with("out_1"))
+        }));
+
+        let plan = plan.absolute();
+        let current_dir = std::env::current_dir().unwrap();
+        assert_eq!(plan.root, current_dir.join("out_1"));
+        assert!(plan.directories.iter().all(|path| path.is_absolute()));
+        assert!(plan.transfers.iter().all(|transfer| {
+            transfer.source.starts_with(&current_dir) && transfer.destination.is_absolute()
+        }));
+        assert!(plan
+            .collisions
+            .iter()
+            .all(|collision| collision.source.is_absolute() && collision.other.is_absolute()));
+    }
+}
@@ -0,0 +1,181 @@
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressState, ProgressStyle};
+use std::{
+    fmt::Write,
+    io::IsTerminal,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Interval between plain log lines, which replace the progress bar, when the stderr isn't a terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Progress of a phase, which is shown as a progress bar, when the stderr is a terminal,
+/// and as periodic plain log lines otherwise.
+pub struct Progress {
+    bar: ProgressBar,
+    kind: Kind,
+    /// Amount of the completed files, while the position of the bar may count bytes
+    files: Arc<AtomicU64>,
+    /// Time of the last log line, when the progress bar isn't shown
+    logged: Option<Mutex<Instant>>,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    /// Files are counted, while their total amount isn't known
+    Scan,
+    /// Transferred bytes are counted out of the known total
+    Transfer { files: u64 },
+}
+
+impl Progress {
+    /// Progress of scanning a directory, which amount of files isn't known beforehand.
+    pub fn scan() -> Self {
+        let progress = Self::new(ProgressBar::new_spinner(), Kind::Scan);
+        progress.bar.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.blue} Scanning... {pos} files ({per_sec}) [{elapsed}]",
+            )
+            .unwrap(),
+        );
+        progress.bar.enable_steady_tick(Duration::from_millis(100));
+        progress
+    }
+
+    /// Progress of transferring [`files`] of [`bytes`] in total. ETA is estimated by the bytes.
+    pub fn transfer(files: u64, bytes: u64) -> Self {
+        let progress = Self::new(ProgressBar::new(bytes), Kind::Transfer { files });
+
+        let completed = Arc::clone(&progress.files);
+        let files_key = move |_: &ProgressState, w: &mut dyn Write| {
+            write!(w, "{}/{files} files", completed.load(Ordering::Relaxed)).unwrap()
+        };
+        let completed = Arc::clone(&progress.files);
+        let files_per_sec_key = move |state: &ProgressState, w: &mut dyn Write| {
+            let rate = completed.load(Ordering::Relaxed) as f64 / state.elapsed().as_secs_f64();
+            write!(
+                w,
+                "{:.1} files/s",
+                if rate.is_finite() { rate } else { 0.0 }
+            )
+            .unwrap()
+        };
+        progress.bar.set_style(
+            ProgressStyle::with_template(
+                "[{elapsed_precise}] [{wide_bar:.blue}] {binary_bytes}/{binary_total_bytes} \
+                 ({binary_bytes_per_sec}, {files}, {files_per_sec}) ETA {eta} {msg}",
+            )
+            .unwrap()
+            .with_key("files", files_key)
+            .with_key("files_per_sec", files_per_sec_key)
+            .progress_chars("#>-"),
+        );
+        progress
+    }
+
+    fn new(bar: ProgressBar, kind: Kind) -> Self {
+        let (bar, logged) = if !log::log_enabled!(log::Level::Info) {
+            // Quiet runs show no progress at all
+            bar.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+            (bar, None)
+        } else if std::io::stderr().is_terminal() {
+            (bar, None)
+        } else {
+            // The hidden bar still measures the rates and ETA for the log lines
+            bar.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+            (bar, Some(Mutex::new(Instant::now())))
+        };
+
+        Self {
+            bar,
+            kind,
+            files: Arc::new(AtomicU64::new(0)),
+            logged,
+        }
+    }
+
+    /// Counts a completed file of [`bytes`] size. Scanned files are counted regardless of their size.
+    pub fn inc(&self, bytes: u64) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+        match self.kind {
+            Kind::Scan => self.bar.inc(1),
+            Kind::Transfer { .. } => self.bar.inc(bytes),
+        }
+
+        if let Some(logged) = &self.logged {
+            let mut logged = logged.lock().unwrap();
+            if logged.elapsed() >= LOG_INTERVAL {
+                *logged = Instant::now();
+                log::info!("{}", self.line());
+            }
+        }
+    }
+
+    /// Shows the [`patient`], which files are being processed.
+    pub fn set_patient(&self, patient: &str) {
+        self.bar.set_message(patient.to_string());
+    }
+
+    /// Removes the progress bar or logs the final line, when it isn't shown.
+    pub fn finish(&self) {
+        if self.logged.is_some() {
+            log::info!("{}", self.line());
+        }
+        self.bar.finish_and_clear();
+    }
+
+    /// Plain description of the progress, which replaces the progress bar.
+    fn line(&self) -> String {
+        let files = self.files.load(Ordering::Relaxed);
+        let elapsed = self.bar.elapsed();
+
+        match self.kind {
+            Kind::Scan => format!("Scanned {files} files in {}", HumanDuration(elapsed)),
+            Kind::Transfer { files: total } => {
+                let rate = |amount: f64| amount / elapsed.as_secs_f64().max(f64::EPSILON);
+                let mut line = format!(
+                    "Transferred {files}/{total} files, {}/{} ({}/s, {:.1} files/s), ETA {}",
+                    HumanBytes(self.bar.position()),
+                    HumanBytes(self.bar.length().unwrap_or_default()),
+                    HumanBytes(rate(self.bar.position() as f64) as u64),
+                    rate(files as f64),
+                    HumanDuration(self.bar.eta()),
+                );
+                if !self.bar.message().is_empty() {
+                    write!(line, ", patient {}", self.bar.message()).unwrap();
+                }
+                line
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_counts_files_and_bytes() {
+        let progress = Progress::transfer(3, 300);
+        progress.set_patient("98.12.21");
+        progress.inc(100);
+        progress.inc(50);
+
+        assert_eq!(progress.files.load(Ordering::Relaxed), 2);
+        assert_eq!(progress.bar.position(), 150);
+        let line = progress.line();
+        assert!(
+            line.starts_with("Transferred 2/3 files, 150 B/300 B"),
+            "{line}"
+        );
+        assert!(line.ends_with("patient 98.12.21"), "{line}");
+
+        let progress = Progress::scan();
+        progress.inc(100);
+        assert_eq!(progress.bar.position(), 1);
+        assert!(progress.line().starts_with("Scanned 1 files"));
+    }
+}
@@ -1,5 +1,8 @@
-use clap::{command, Parser};
-use options::{CatalogOptions, RestructOptions};
+use clap::Parser;
+use options::{
+    AnonymizeOptions, CatalogOptions, ReidentifyOptions, RestructOptions, VerifyOptions,
+};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(version, about)]
@@ -7,6 +10,15 @@ use options::{CatalogOptions, RestructOptions};
 pub struct Args {
     #[command(subcommand)]
     pub command: Command,
+    /// Print more messages: `-v` adds debug messages and timings of the phases, `-vv` adds each transferred file
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Print only errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Append messages with timestamps to the file, including the debug ones
+    #[arg(long, value_name = "FILE", global = true)]
+    pub log_file: Option<PathBuf>,
 }
 
 #[derive(Parser)]
@@ -16,19 +28,178 @@ pub enum Command {
     Catalog(CatalogOptions),
     /// Create a new directory with a restructured hierarchy based on person IDs, as in a catalog output
     Restruct(RestructOptions),
+    /// Check files of a restructured directory against the checksums, which have been written by `restruct --verify`
+    Verify(VerifyOptions),
+    /// Copy de-identified DICOM files into a new directory according to the DICOM PS3.15 Basic Profile
+    Anonymize(AnonymizeOptions),
+    /// Print original patient IDs and UIDs of the pseudonyms, which have been recorded in the vault by `anonymize --vault`
+    Reidentify(ReidentifyOptions),
 }
 
 pub(crate) mod options {
+    use glob::Pattern;
+    use regex::Regex;
+    use serde::{Deserialize, Serialize};
     use std::{ffi::OsString, path::PathBuf};
 
+    use crate::{
+        filter::Filter,
+        header::TagList,
+        jobs::Jobs,
+        layout::{Layout, DEFAULT_LAYOUT},
+        patient::DEFAULT_UNKNOWN_PATIENT,
+    };
+
+    /// Format, in which the catalog is printed to the stdout
+    #[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum CatalogFormat {
+        /// Table for each patient with the sub-tree of the original directory
+        #[default]
+        Table,
+        /// Row for each DICOM file, printed while the directory is being traversed
+        Csv,
+        /// Array of patients with their attributes, studies, series and sorted paths
+        Json,
+        /// JSON object for each DICOM file, printed while the directory is being traversed
+        Ndjson,
+    }
+
+    /// What happens, when several files are restructured into the same path
+    #[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum CollisionPolicy {
+        /// Add `_1`, `_2`, ... suffix to the name of the file
+        #[default]
+        Suffix,
+        /// Name the file after its `SOPInstanceUID`, falling back to a suffix
+        SopUid,
+        /// Skip the file, when its content is identical to the other one, and add a suffix otherwise
+        SkipIdentical,
+        /// Stop before restructuring any files
+        Fail,
+    }
+
+    /// Format, in which the plan of `restruct --dry-run` is printed to the stdout
+    #[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum PlanFormat {
+        /// `source -> destination` line for each file, followed by directories to create and the total size
+        #[default]
+        Text,
+        /// `Kind,Source,Destination,Details` rows: a `file` for each transfer, a `directory` for each one to create,
+        /// a `collision` for each one along with its resolution and the `total` size in bytes
+        Csv,
+        /// Whole plan, which can be executed later via `--plan`
+        Json,
+    }
+
+    /// The way files are placed into the new directory
+    #[derive(
+        clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+    )]
+    #[serde(rename_all = "lowercase")]
+    pub enum TransferMode {
+        /// Copy content of the files
+        #[default]
+        Copy,
+        /// Move the files, which costs only a rename on the same file system
+        Move,
+        /// Create hard links to the files on the same file system
+        Hardlink,
+        /// Create symbolic links to the absolute paths of the files
+        Symlink,
+        /// Share content of the files via copy-on-write, where the file system supports it
+        Reflink,
+    }
+
+    /// Option of the de-identification, which retains attributes removed by the basic profile
+    #[derive(
+        clap::ValueEnum,
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        PartialOrd,
+        Ord,
+        Hash,
+        Serialize,
+        Deserialize,
+    )]
+    #[serde(rename_all = "kebab-case")]
+    pub enum RetainOption {
+        /// Keep dates and times of the studies, series and acquisitions
+        LongitudinalDates,
+        /// Keep sex, age, size, weight and other characteristics of the patients
+        PatientCharacteristics,
+        /// Keep names, serial numbers and UIDs of the devices
+        DeviceIdentity,
+        /// Keep names and addresses of the institutions
+        InstitutionIdentity,
+        /// Keep the original study, series and instance UIDs
+        Uids,
+    }
+
     #[derive(clap::Args)]
     pub struct RestructOptions {
         /// Path to the directory, which will be restructured
-        #[arg(short, long)]
-        pub path: PathBuf,
-        /// Person IDs(separated by `,`), which DICOM files will be restructured in a new directory
-        #[arg(long, value_delimiter = ',')]
-        pub ids: Option<Vec<OsString>>,
+        #[arg(short, long, required_unless_present_any = ["plan", "resume"])]
+        pub path: Option<PathBuf>,
+        /// Existing directory, into which files are restructured. By default, a new `dicat_<timestamp>` directory
+        /// is created in the current one
+        #[arg(short, long, value_name = "DIR")]
+        pub output: Option<PathBuf>,
+        /// Template of the path of each file inside of the new directory, where `{...}` placeholders are replaced
+        /// with DICOM attributes, e.g. `{PatientID}/{StudyDate}_{StudyDescription}/{SeriesNumber:03}_{Modality}/{InstanceNumber:05}.dcm`
+        #[arg(long, value_name = "TEMPLATE", default_value = DEFAULT_LAYOUT)]
+        pub layout: Layout,
+        /// What happens, when several files are restructured into the same path. All collisions are reported at the end
+        #[arg(long, value_enum, default_value_t)]
+        pub on_collision: CollisionPolicy,
+        /// The way files are placed into the new directory. Files, which can't be moved or linked, e.g. across file systems, are copied
+        #[arg(long, value_enum, default_value_t)]
+        pub mode: TransferMode,
+        /// Compare SHA-256 checksums of each transferred file and its source, and write them into `checksums.sha256`
+        /// in each patient's directory
+        #[arg(long)]
+        pub verify: bool,
+        /// Amount of files, which are transferred concurrently, or `auto` to adjust it according to the measured throughput
+        #[arg(
+            short,
+            long,
+            env = "DICAT_JOBS",
+            default_value = "4",
+            value_name = "N|auto"
+        )]
+        pub jobs: Jobs,
+        /// Print the plan of the restructuring without transferring any files
+        #[arg(long)]
+        pub dry_run: bool,
+        /// Format of the plan, which is printed by `--dry-run`
+        #[arg(long, value_enum, default_value_t, requires = "dry_run")]
+        pub plan_format: PlanFormat,
+        /// Execute the plan, which has been printed by `--dry-run --plan-format json`, without traversing the directory again
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["path", "output", "layout", "on_collision", "SelectionArgs", "IdentificationArgs"]
+        )]
+        pub plan: Option<PathBuf>,
+        /// Resume the interrupted restructuring into the directory, transferring only the files, which are missing there
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = ["path", "output", "layout", "on_collision", "mode", "verify", "dry_run", "plan", "SelectionArgs", "IdentificationArgs"]
+        )]
+        pub resume: Option<PathBuf>,
+        #[command(flatten)]
+        pub selection: SelectionArgs,
+        #[command(flatten)]
+        pub identification: IdentificationArgs,
+        /// Write every skipped or failed file along with the reason into the .JSON file
+        #[arg(long, value_name = "FILE")]
+        pub report: Option<PathBuf>,
+        /// Fail, when any of the files have been skipped, e.g. because they aren't DICOM files
+        #[arg(long)]
+        pub strict: bool,
     }
 
     #[derive(clap::Args)]
@@ -36,11 +207,175 @@ pub(crate) mod options {
         #[arg(short, long)]
         /// Path to the directory, which fiels will be viewed in a catalog format
         pub path: PathBuf,
-        #[arg(short, long)]
-        /// Print names, IDs, and paths of DICOM files in a directory in .CSV format, preserving the original directory hierarchy
+        #[arg(short, long, conflicts_with = "format")]
+        /// Print names, IDs, and paths of DICOM files in a directory in .CSV format, preserving the original directory hierarchy. Same as `--format csv`
         pub as_csv: bool,
-        /// Person IDs(separated by `,`), which DICOM files will be viewed in a catalog format
+        /// Format of the printed catalog
+        #[arg(short, long, value_enum, default_value_t)]
+        pub format: CatalogFormat,
+        /// Tags(separated by `,`), which values are printed for each DICOM file in `csv` and `ndjson` formats.
+        /// Accepts keywords and `(gggg,eeee)`. Study and series attributes are printed in `csv` by default
+        #[arg(long)]
+        pub tags: Option<TagList>,
+        /// Single ASCII character, which separates values in `csv` format. Use `tab` for TSV
+        #[arg(long, default_value = ",", value_parser = parse_delimiter)]
+        pub delimiter: u8,
+        /// Don't print the header row in `csv` format
+        #[arg(long)]
+        pub no_header: bool,
+        #[command(flatten)]
+        pub selection: SelectionArgs,
+        #[command(flatten)]
+        pub identification: IdentificationArgs,
+        /// Write every skipped or failed file along with the reason into the .JSON file
+        #[arg(long, value_name = "FILE")]
+        pub report: Option<PathBuf>,
+        /// Fail, when any of the files have been skipped, e.g. because they aren't DICOM files
+        #[arg(long)]
+        pub strict: bool,
+    }
+
+    #[derive(clap::Args)]
+    pub struct AnonymizeOptions {
+        /// Path to the directory, which files will be de-identified
+        #[arg(short, long)]
+        pub path: PathBuf,
+        /// Existing directory, into which de-identified files are written as `<study>/<series>/<instance>.dcm`.
+        /// By default, a new `dicat_<timestamp>` directory is created in the current one
+        #[arg(short, long, value_name = "DIR")]
+        pub output: Option<PathBuf>,
+        /// Options(separated by `,`) of the profile, which retain some of the attributes
+        #[arg(long, value_enum, value_delimiter = ',', value_name = "OPTIONS")]
+        pub retain: Vec<RetainOption>,
+        /// Replace person IDs and names with pseudonyms, which are derived with the key from the vault, so that they and
+        /// the new UIDs are the same across runs. The vault is created, when it doesn't exist, and records the pseudonyms
+        /// along with the originals, so it's only readable by its owner
+        #[arg(long, value_name = "FILE")]
+        pub vault: Option<PathBuf>,
+        /// Amount of files, which are de-identified concurrently, or `auto` to adjust it according to the measured throughput
+        #[arg(
+            short,
+            long,
+            env = "DICAT_JOBS",
+            default_value = "4",
+            value_name = "N|auto"
+        )]
+        pub jobs: Jobs,
+        #[command(flatten)]
+        pub selection: SelectionArgs,
+        /// Write every skipped or failed file along with the reason into the .JSON file
+        #[arg(long, value_name = "FILE")]
+        pub report: Option<PathBuf>,
+        /// Fail, when any of the files have been skipped, e.g. because they aren't DICOM files
+        #[arg(long)]
+        pub strict: bool,
+    }
+
+    /// Which patients and files are selected by `catalog`, `restruct` and `anonymize`
+    #[derive(clap::Args, Default)]
+    pub struct SelectionArgs {
+        /// Person IDs(separated by `,`), which DICOM files are selected
         #[arg(long, value_delimiter = ',')]
         pub ids: Option<Vec<OsString>>,
+        /// File with person IDs, one per line, which are selected in addition to `--ids`. Use `-` to read them from the stdin
+        #[arg(long, value_name = "FILE")]
+        pub ids_file: Option<PathBuf>,
+        /// Column of the `--ids-file`, which contains person IDs, when the file is a .CSV file with a header row
+        #[arg(long, value_name = "NAME", requires = "ids_file")]
+        pub ids_column: Option<String>,
+        /// Glob patterns(separated by `,`) of person IDs, which DICOM files are selected, e.g. `CMB-GEC-*`
+        #[arg(long, value_delimiter = ',', value_name = "PATTERNS")]
+        pub ids_glob: Option<Vec<Pattern>>,
+        /// Regular expression of person IDs, which DICOM files are selected. Can be repeated
+        #[arg(long, value_name = "REGEX")]
+        pub ids_regex: Option<Vec<Regex>>,
+        /// Person IDs(separated by `,`), which DICOM files aren't selected, even if they match other options
+        #[arg(long, value_delimiter = ',')]
+        pub exclude_ids: Option<Vec<OsString>>,
+        /// Match person IDs case-insensitively
+        #[arg(long)]
+        pub ignore_case: bool,
+        /// Expression over DICOM attributes, which selects files,
+        /// e.g. `Modality == "CT" && StudyDate >= 20230101 && BodyPartExamined ~ "CHEST"`
+        #[arg(long = "where", value_name = "EXPRESSION")]
+        pub filter: Option<Filter>,
+        /// Keep an index of the directory in the user's cache directory and read only new or changed files
+        #[arg(long)]
+        pub index: bool,
+    }
+
+    /// How patients of the files without a usable `PatientID` are identified by `catalog` and `restruct`
+    #[derive(clap::Args)]
+    pub struct IdentificationArgs {
+        /// Person ID, which files without a usable `PatientID` are grouped under
+        #[arg(long, value_name = "ID", default_value = DEFAULT_UNKNOWN_PATIENT, value_parser = clap::builder::NonEmptyStringValueParser::new())]
+        pub unknown_patient: String,
+        /// Identify patients of the files without a usable `PatientID` by `OtherPatientIDs` or `IssuerOfPatientID`,
+        /// before grouping them under `--unknown-patient`
+        #[arg(long)]
+        pub patient_id_fallback: bool,
+    }
+
+    #[derive(clap::Args)]
+    pub struct ReidentifyOptions {
+        /// Vault, which has been written by `anonymize --vault` and is only readable by its owner
+        #[arg(long, value_name = "FILE")]
+        pub vault: PathBuf,
+        /// Reason of the re-identification, e.g. the approval of the review board, which is recorded in the audit log
+        /// next to the vault
+        #[arg(long)]
+        pub reason: String,
+        /// Pseudonyms or new UIDs, which are re-identified. All of the recorded ones are printed, when none are given
+        pub pseudonyms: Vec<String>,
+    }
+
+    #[derive(clap::Args)]
+    pub struct VerifyOptions {
+        /// Path to the restructured directory, which files are checked against their `checksums.sha256`
+        #[arg(short, long)]
+        pub path: PathBuf,
+    }
+
+    /// Parses a delimiter of the `csv` format, which has to be a single ASCII character or `tab`.
+    fn parse_delimiter(s: &str) -> Result<u8, String> {
+        match s {
+            "tab" | "\\t" => Ok(b'\t'),
+            _ => match s.as_bytes() {
+                [delimiter] if delimiter.is_ascii() => Ok(*delimiter),
+                _ => Err(format!("`{s}` isn't a single ASCII character")),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saved_runs_conflict_with_the_selection() {
+        let selection = [
+            ["--ids", "a"],
+            ["--ids-file", "ids.txt"],
+            ["--ids-glob", "a*"],
+            ["--ids-regex", "a"],
+            ["--exclude-ids", "a"],
+            ["--ignore-case", ""],
+            ["--where", "Modality == \"CT\""],
+            ["--index", ""],
+            ["--unknown-patient", "a"],
+            ["--patient-id-fallback", ""],
+        ];
+
+        for saved in [["--plan", "plan.json"], ["--resume", "dicat_1700000000"]] {
+            let parse = |arg: &[&str]| {
+                let args = ["dicat", "restruct"].iter().chain(&saved).chain(arg);
+                Args::try_parse_from(args.filter(|arg| !arg.is_empty()))
+            };
+            assert!(parse(&[]).is_ok());
+            for arg in &selection {
+                assert!(parse(arg).is_err(), "{saved:?} {arg:?}");
+            }
+        }
     }
 }
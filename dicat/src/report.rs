@@ -0,0 +1,236 @@
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::errors::{CliError, CliResult};
+
+/// Reason, why a file has been skipped, has failed, or has been assigned to a substitute patient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// The directory entry or the file can't be read
+    Unreadable,
+    /// The file isn't a valid DICOM file
+    NotDicom,
+    /// The DICOM file doesn't contain a usable `PatientID`, so it's grouped under the unknown patient
+    MissingPatientId,
+    /// The DICOM file doesn't contain a usable `PatientID`, so it's identified by one of the fallback tags
+    PatientIdFallback,
+    /// The file couldn't be transferred into the new directory
+    TransferFailed,
+    /// The transferred file doesn't match its source
+    ChecksumMismatch,
+}
+
+impl Reason {
+    /// Checks whether the file has failed, rather than has been skipped on purpose.
+    pub fn is_failure(self) -> bool {
+        matches!(self, Self::TransferFailed | Self::ChecksumMismatch)
+    }
+
+    /// Checks whether the file has been processed under a substitute patient's ID, rather than skipped.
+    pub fn is_substitution(self) -> bool {
+        matches!(self, Self::MissingPatientId | Self::PatientIdFallback)
+    }
+
+    /// Level, which the file is logged with. Non-DICOM files are common in the directories, so they are only
+    /// debug messages.
+    pub fn level(self) -> log::Level {
+        match self {
+            Self::TransferFailed | Self::ChecksumMismatch => log::Level::Error,
+            Self::Unreadable => log::Level::Warn,
+            Self::NotDicom | Self::MissingPatientId | Self::PatientIdFallback => log::Level::Debug,
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::Unreadable => "unreadable",
+            Self::NotDicom => "not DICOM",
+            Self::MissingPatientId => "under the unknown patient",
+            Self::PatientIdFallback => "identified by a fallback tag",
+            Self::TransferFailed => "not transferred",
+            Self::ChecksumMismatch => "not matching the source",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// Single skipped, failed or substituted file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub reason: Reason,
+    pub message: String,
+}
+
+/// Files, which have been skipped, have failed or have been assigned to substitute patients during a run,
+/// collected from all of the threads.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::report::{Reason, Report};
+///
+/// let report = Report::default();
+/// report.add("notes.txt", Reason::NotDicom, "Could not read preamble bytes");
+///
+/// assert_eq!(report.skipped(), 1);
+/// assert_eq!(report.failed(), 0);
+/// assert!(report.finish(None, false).is_ok());
+/// assert!(report.finish(None, true).is_err());
+/// ```
+#[derive(Debug, Default)]
+pub struct Report {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Report {
+    pub fn add(&self, path: impl Into<PathBuf>, reason: Reason, message: impl ToString) {
+        self.entries.lock().unwrap().push(Entry {
+            path: path.into(),
+            reason,
+            message: message.to_string(),
+        });
+    }
+
+    /// Logs the file along with the [`reason`] and adds it to the report.
+    pub fn log_and_add(&self, path: impl Into<PathBuf>, reason: Reason, message: impl ToString) {
+        let (path, message) = (path.into(), message.to_string());
+        log::log!(reason.level(), "{} is {reason}: {message}", path.display());
+        self.add(path, reason, message);
+    }
+
+    /// Entries sorted by their paths, since they are collected in no particular order.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut entries = self.entries.lock().unwrap().clone();
+        entries.sort_by(|a, b| (&a.path, a.reason).cmp(&(&b.path, b.reason)));
+        entries
+    }
+
+    /// Amount of the files, which have been skipped on purpose.
+    pub fn skipped(&self) -> usize {
+        self.count(|reason| !reason.is_failure() && !reason.is_substitution())
+    }
+
+    /// Amount of the files, which have been processed under substitute patient's IDs.
+    pub fn substituted(&self) -> usize {
+        self.count(Reason::is_substitution)
+    }
+
+    /// Amount of the files, which have failed.
+    pub fn failed(&self) -> usize {
+        self.count(Reason::is_failure)
+    }
+
+    fn count(&self, predicate: impl Fn(Reason) -> bool) -> usize {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| predicate(entry.reason))
+            .count()
+    }
+
+    /// Prints the summary and writes all of the entries into the [`report_path`] as JSON, when it's provided.
+    /// Fails, when any of the files have failed, or have been skipped in the [`strict`] mode.
+    pub fn finish(&self, report_path: Option<&Path>, strict: bool) -> CliResult<()> {
+        let entries = self.entries();
+
+        // Each file has been logged, when it was skipped or has failed, so only the amounts are summarized
+        let mut reasons: BTreeMap<Reason, usize> = BTreeMap::new();
+        for entry in &entries {
+            *reasons.entry(entry.reason).or_default() += 1;
+        }
+        let summarize = |predicate: fn(Reason) -> bool| {
+            reasons
+                .iter()
+                .filter(|(reason, _)| predicate(**reason))
+                .map(|(reason, amount)| format!("{amount} {reason}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if self.skipped() > 0 {
+            log::warn!(
+                "{} files have been skipped ({})",
+                self.skipped(),
+                summarize(|reason| !reason.is_failure() && !reason.is_substitution())
+            );
+        }
+        if self.substituted() > 0 {
+            log::warn!(
+                "{} files don't have a usable PatientID ({})",
+                self.substituted(),
+                summarize(Reason::is_substitution)
+            );
+        }
+        if self.failed() > 0 {
+            log::warn!(
+                "{} files have failed ({})",
+                self.failed(),
+                summarize(Reason::is_failure)
+            );
+        }
+
+        if let Some(report_path) = report_path {
+            let report_error = || CliError::ReportError(report_path.to_path_buf());
+            let file = std::fs::File::create(report_path).map_err(|_| report_error())?;
+            serde_json::to_writer_pretty(file, &entries).map_err(|_| report_error())?;
+        }
+
+        if self.failed() > 0 {
+            Err(CliError::FilesFailed(self.failed()))
+        } else if strict && self.skipped() > 0 {
+            Err(CliError::FilesSkipped(self.skipped()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_is_written_as_json() -> CliResult<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let report_path = dir.path().join("errors.json");
+
+        let report = Report::default();
+        report.add("b.dcm", Reason::MissingPatientId, "PatientID is absent");
+        report.add("a.txt", Reason::NotDicom, "Could not read preamble bytes");
+        report.finish(Some(&report_path), false)?;
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&report_path).unwrap()).unwrap();
+        assert_eq!(
+            written,
+            serde_json::json!([
+                {"path": "a.txt", "reason": "not_dicom", "message": "Could not read preamble bytes"},
+                {"path": "b.dcm", "reason": "missing_patient_id", "message": "PatientID is absent"},
+            ])
+        );
+
+        // Substituted files don't fail the run even in the strict mode
+        report.add(
+            "d.dcm",
+            Reason::PatientIdFallback,
+            "IssuerOfPatientID is used",
+        );
+        assert_eq!(report.substituted(), 2);
+        assert_eq!(report.skipped(), 1);
+
+        report.add("c.dcm", Reason::TransferFailed, "No space left on device");
+        assert!(matches!(
+            report.finish(None, false),
+            Err(CliError::FilesFailed(1))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+use dicom::core::Tag;
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    ffi::OsString,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::{CliError, CliResult},
+    filter::Filter,
+    header::Attributes,
+    patient::Identification,
+    prompt_parser::options::SelectionArgs,
+    report::Reason,
+};
+
+/// Decides, which DICOM files are selected, based on IDs of their patients and the filter expression.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::selection::Selection;
+///
+/// let selection = Selection::new(Some(vec!["98.12.21".into()]), None)
+///     .ids_glob(vec!["cmb-gec-*".parse().unwrap()])
+///     .exclude_ids(vec!["CMB-GEC-MSB-06857".into()])
+///     .ignore_case(true);
+///
+/// let attributes = Default::default();
+/// assert!(selection.matches("98.12.21", &attributes));
+/// assert!(selection.matches("CMB-GEC-MSB-00001", &attributes));
+/// assert!(!selection.matches("CMB-GEC-MSB-06857", &attributes));
+/// assert!(!selection.matches("CMB-LCA-MSB-00001", &attributes));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    ids: HashSet<String>,
+    globs: Vec<Pattern>,
+    regexes: Vec<Regex>,
+    excluded_ids: HashSet<String>,
+    /// [`Self::ids`] and [`Self::excluded_ids`] in the form, which IDs of the files are looked up in,
+    /// i.e. lowercased, when the case is ignored
+    normalized_ids: HashSet<String>,
+    normalized_excluded_ids: HashSet<String>,
+    ignore_case: bool,
+    filter: Option<Filter>,
+    identification: Identification,
+}
+
+impl Selection {
+    /// Creates a selection of files, which belong to one of the patients with [`ids`] and satisfy the [`filter`].
+    /// Omitted [`ids`] or [`filter`] don't restrict the selection.
+    pub fn new(ids: Option<Vec<OsString>>, filter: Option<Filter>) -> Self {
+        let ids = ids
+            .unwrap_or_default()
+            .iter()
+            .map(|id| id.to_string_lossy().into_owned())
+            .collect();
+
+        let selection = Self {
+            ids,
+            filter,
+            ..Default::default()
+        };
+        selection.normalized()
+    }
+
+    /// Additionally selects patients, which IDs match one of the glob [`patterns`].
+    pub fn ids_glob(mut self, patterns: Vec<Pattern>) -> Self {
+        self.globs.extend(patterns);
+        self
+    }
+
+    /// Additionally selects patients, which IDs match one of the [`regexes`].
+    pub fn ids_regex(mut self, regexes: Vec<Regex>) -> Self {
+        let ignore_case = self.ignore_case;
+        self.regexes.extend(
+            regexes
+                .into_iter()
+                .map(|regex| with_case(regex, ignore_case)),
+        );
+        self
+    }
+
+    /// Excludes patients with [`ids`], even when they are selected otherwise.
+    pub fn exclude_ids(mut self, ids: Vec<OsString>) -> Self {
+        self.excluded_ids
+            .extend(ids.iter().map(|id| id.to_string_lossy().into_owned()));
+        self.normalized()
+    }
+
+    /// Makes all kinds of ID matching case-insensitive.
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self.regexes = self
+            .regexes
+            .into_iter()
+            .map(|regex| with_case(regex, ignore_case))
+            .collect();
+        self.normalized()
+    }
+
+    /// Identifies patients of the files without a usable `PatientID` according to the [`identification`].
+    pub fn identification(mut self, identification: Identification) -> Self {
+        self.identification = identification;
+        self
+    }
+
+    /// Tags, besides the patient's ID, which values are needed to decide whether a file is selected.
+    pub fn tags(&self) -> Vec<Tag> {
+        let mut tags = self.filter.as_ref().map(Filter::tags).unwrap_or_default();
+        tags.extend(self.identification.tags());
+        tags
+    }
+
+    /// Makes sure, that the [`attributes`] contain the patient's ID, which the file is selected by.
+    /// See [`Identification::identify`].
+    pub fn identify(&self, attributes: &mut Attributes) -> Option<(Reason, String)> {
+        self.identification.identify(attributes)
+    }
+
+    /// Checks whether the file of the patient with [`id`] and the given [`attributes`] is selected.
+    pub fn matches(&self, id: &str, attributes: &Attributes) -> bool {
+        self.id_matches(id)
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(attributes))
+    }
+
+    /// IDs, which were listed explicitly, but aren't among the [`found`] ones. Excluded IDs are never missing.
+    pub fn missing_ids<I, S>(&self, found: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let found: HashSet<String> = found
+            .into_iter()
+            .map(|id| self.normalize(id.as_ref()).into_owned())
+            .collect();
+
+        let mut missing: Vec<String> = self
+            .ids
+            .iter()
+            .filter(|id| {
+                let id = self.normalize(id);
+                !found.contains(id.as_ref()) && !self.normalized_excluded_ids.contains(id.as_ref())
+            })
+            .cloned()
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    fn id_matches(&self, id: &str) -> bool {
+        let match_options = MatchOptions {
+            case_sensitive: !self.ignore_case,
+            ..Default::default()
+        };
+        let normalized = self.normalize(id);
+
+        let selects_all = self.ids.is_empty() && self.globs.is_empty() && self.regexes.is_empty();
+        let included = selects_all
+            || self.normalized_ids.contains(normalized.as_ref())
+            || self
+                .globs
+                .iter()
+                .any(|glob| glob.matches_with(id, match_options))
+            || self.regexes.iter().any(|regex| regex.is_match(id));
+
+        included && !self.normalized_excluded_ids.contains(normalized.as_ref())
+    }
+
+    /// The [`id`] in the form, which is compared with the listed IDs.
+    fn normalize<'a>(&self, id: &'a str) -> Cow<'a, str> {
+        if self.ignore_case {
+            Cow::Owned(id.to_lowercase())
+        } else {
+            Cow::Borrowed(id)
+        }
+    }
+
+    /// Rebuilds the sets of the listed IDs, which are looked up, after the IDs or the case sensitivity have changed,
+    /// so that they aren't normalized for each file.
+    fn normalized(mut self) -> Self {
+        let normalize = |ids: &HashSet<String>| {
+            ids.iter()
+                .map(|id| self.normalize(id).into_owned())
+                .collect()
+        };
+        let (ids, excluded_ids) = (normalize(&self.ids), normalize(&self.excluded_ids));
+        self.normalized_ids = ids;
+        self.normalized_excluded_ids = excluded_ids;
+        self
+    }
+}
+
+impl SelectionArgs {
+    /// Selection of the listed IDs, including the ones of the `--ids-file`, the patterns and the filter.
+    /// Patients of the files without a usable `PatientID` are identified by default.
+    pub fn selection(&self) -> CliResult<Selection> {
+        let mut ids = self.ids.clone();
+        if let Some(ids_file) = &self.ids_file {
+            ids.get_or_insert_default()
+                .extend(read_ids_file(ids_file, self.ids_column.as_deref())?);
+        }
+
+        Ok(Selection::new(ids, self.filter.clone())
+            .ids_glob(self.ids_glob.clone().unwrap_or_default())
+            .ids_regex(self.ids_regex.clone().unwrap_or_default())
+            .exclude_ids(self.exclude_ids.clone().unwrap_or_default())
+            .ignore_case(self.ignore_case))
+    }
+}
+
+/// Reads person IDs from the file at [`path`] or from the stdin, when [`path`] is `-`.
+/// Without a [`column`] each non-empty line is an ID, otherwise the file is read as a .CSV file with a header row
+/// and IDs are taken from the [`column`]. Fails, when the list doesn't contain any IDs.
+pub fn read_ids_file(path: &Path, column: Option<&str>) -> CliResult<Vec<OsString>> {
+    let ids_file_error = |_| CliError::IdsFileError(path.to_path_buf());
+    let reader: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(std::fs::File::open(path).map_err(ids_file_error)?)
+    };
+
+    let ids = match column {
+        Some(column) => {
+            read_ids_column(reader, column).ok_or(CliError::IdsFileError(path.into()))?
+        }
+        None => BufReader::new(reader)
+            .lines()
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(ids_file_error)?,
+    };
+    let ids: Vec<OsString> = ids
+        .iter()
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+        .map(OsString::from)
+        .collect();
+
+    if ids.is_empty() {
+        Err(CliError::IdsFileError(PathBuf::from(path)))
+    } else {
+        Ok(ids)
+    }
+}
+
+/// Reads values of the [`column`] out of .CSV data with a header row.
+fn read_ids_column<R: Read>(reader: R, column: &str) -> Option<Vec<String>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let position = reader
+        .headers()
+        .ok()?
+        .iter()
+        .position(|header| header.trim() == column)?;
+
+    reader
+        .records()
+        .map(|record| Some(record.ok()?.get(position).unwrap_or_default().to_string()))
+        .collect()
+}
+
+/// Rebuilds the [`regex`] to match case-insensitively, when [`ignore_case`] is set.
+fn with_case(regex: Regex, ignore_case: bool) -> Regex {
+    RegexBuilder::new(regex.as_str())
+        .case_insensitive(ignore_case)
+        .build()
+        // The pattern has already been compiled once
+        .unwrap_or(regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_read_ids_file() -> CliResult<()> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "CMB-GEC-MSB-06857\n\n  98.12.21 \r\nNA-A001").unwrap();
+        let ids = read_ids_file(file.path(), None)?;
+        assert_eq!(ids, ["CMB-GEC-MSB-06857", "98.12.21", "NA-A001"]);
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "name,PatientID\nDoe^John,98.12.21\n\"Doe, Jane\",NA-A001"
+        )
+        .unwrap();
+        let ids = read_ids_file(file.path(), Some("PatientID"))?;
+        assert_eq!(ids, ["98.12.21", "NA-A001"]);
+
+        assert!(read_ids_file(file.path(), Some("StudyDate")).is_err());
+        assert!(read_ids_file(Path::new("does_not_exist.txt"), None).is_err());
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert!(read_ids_file(file.path(), None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_ids() {
+        let selection = Selection::new(Some(vec!["A".into(), "b".into(), "C".into()]), None)
+            .exclude_ids(vec!["c".into()])
+            .ignore_case(true);
+        assert_eq!(selection.missing_ids(["a", "D"]), ["b"]);
+
+        let selection = selection.ignore_case(false);
+        assert_eq!(selection.missing_ids(["a", "D"]), ["A", "C", "b"]);
+    }
+
+    #[test]
+    fn test_ids_ignore_case_in_any_order() {
+        let attributes = Default::default();
+        let selection = Selection::new(Some(vec!["Ab".into(), "cD".into()]), None)
+            .ignore_case(true)
+            .exclude_ids(vec!["CD".into()]);
+        assert!(selection.matches("aB", &attributes));
+        assert!(!selection.matches("cd", &attributes));
+
+        let selection = selection.ignore_case(false);
+        assert!(!selection.matches("aB", &attributes));
+        assert!(selection.matches("cD", &attributes));
+    }
+}
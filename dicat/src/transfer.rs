@@ -0,0 +1,350 @@
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{anonymization::Profile, checksum::Checksum, prompt_parser::options::TransferMode};
+
+/// Checks whether [`a`] and [`b`] are on the same file system, so that files can be moved or linked between them.
+/// Where it can't be detected, e.g. when the metadata of either of them can't be read, they are assumed to be
+/// on the same one and each transfer falls back to copying on its own.
+pub fn same_file_system(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        match (std::fs::metadata(a), std::fs::metadata(b)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev(),
+            _ => true,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        true
+    }
+}
+
+impl TransferMode {
+    /// Checks whether the mode writes content of the files anew, so that the destination needs free space for them.
+    pub fn needs_space(self, same_file_system: bool) -> bool {
+        match self {
+            Self::Copy | Self::Reflink => true,
+            Self::Move | Self::Hardlink => !same_file_system,
+            Self::Symlink => false,
+        }
+    }
+
+    /// Checks whether the mode can only be used on a single file system.
+    pub fn needs_same_file_system(self) -> bool {
+        matches!(self, Self::Move | Self::Hardlink | Self::Reflink)
+    }
+
+    /// Past participle of the mode, e.g. "hardlinked", which is used in reports.
+    pub fn done(self) -> &'static str {
+        match self {
+            Self::Copy => "copied",
+            Self::Move => "moved",
+            Self::Hardlink => "hardlinked",
+            Self::Symlink => "symlinked",
+            Self::Reflink => "reflinked",
+        }
+    }
+}
+
+/// Outcome of a single [`transfer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transferred {
+    /// Whether the file has been transferred according to the mode, rather than copied instead
+    pub in_mode: bool,
+    /// Checksums of the source and the destination, when they have been verified. A destination, which shares
+    /// the content with its source, or which can't be read past the page cache, has the checksum of its source
+    pub checksums: Option<(Checksum, Checksum)>,
+}
+
+impl Transferred {
+    /// Checks whether the verified content of the destination differs from the source.
+    pub fn mismatched(&self) -> bool {
+        self.checksums
+            .is_some_and(|(source, destination)| source != destination)
+    }
+}
+
+/// Transfers the file from [`source`] to [`destination`] according to the [`mode`]. When it isn't possible,
+/// e.g. across file systems, the file is copied instead, which is signaled by [`Transferred::in_mode`].
+/// Moved files are removed from the [`source`] even when they have been copied, unless the copy doesn't
+/// match the source. With [`verify`] the source is hashed while it's copied, and the copy is read once again
+/// to compare it, where that read bypasses the page cache. Files, which aren't copied, are hashed only once,
+/// as their destination shares the content with the source. An existing [`destination`] is never replaced,
+/// so the transfer fails with [`io::ErrorKind::AlreadyExists`] instead.
+pub async fn transfer(
+    mode: TransferMode,
+    source: &Path,
+    destination: &Path,
+    same_file_system: bool,
+    verify: bool,
+) -> io::Result<Transferred> {
+    let in_mode = match mode {
+        TransferMode::Copy => {
+            let checksums = copy(source, destination, verify).await?;
+            return Ok(Transferred {
+                in_mode: true,
+                checksums,
+            });
+        }
+        _ if mode.needs_same_file_system() && !same_file_system => false,
+        TransferMode::Move => rename(source, destination).await.is_ok(),
+        TransferMode::Hardlink => tokio::fs::hard_link(source, destination).await.is_ok(),
+        TransferMode::Symlink => symlink(source, destination).await.is_ok(),
+        TransferMode::Reflink => {
+            let (source, destination) = (source.to_path_buf(), destination.to_path_buf());
+            tokio::task::spawn_blocking(move || reflink_copy::reflink(source, destination))
+                .await
+                .is_ok_and(|reflinked| reflinked.is_ok())
+        }
+    };
+
+    let checksums = if !in_mode {
+        copy(source, destination, verify).await?
+    } else if verify {
+        // A moved source can't be read afterwards, but the destination has the same content
+        let checksum = checksum_of(destination).await?;
+        Some((checksum, checksum))
+    } else {
+        None
+    };
+    let transferred = Transferred { in_mode, checksums };
+    if !in_mode && mode == TransferMode::Move && !transferred.mismatched() {
+        tokio::fs::remove_file(source).await?;
+    }
+
+    Ok(transferred)
+}
+
+/// Writes the file from [`source`] de-identified by the [`profile`] to the [`destination`] without blocking the runtime.
+/// The content always differs from the source, so it isn't verified.
+pub async fn anonymize(
+    profile: Arc<Profile>,
+    source: &Path,
+    destination: &Path,
+) -> io::Result<Transferred> {
+    let (source, destination) = (source.to_path_buf(), destination.to_path_buf());
+    tokio::task::spawn_blocking(move || profile.apply(&source, &destination))
+        .await
+        .map_err(io::Error::other)??;
+
+    Ok(Transferred {
+        in_mode: true,
+        checksums: None,
+    })
+}
+
+/// Creates a new file at the [`path`] for writing. It fails, when the path is taken, rather than replacing the file.
+pub fn create_new(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::AlreadyExists => taken(path),
+            _ => err,
+        })
+}
+
+/// Error of a transfer to the [`destination`], which has been taken since the plan was made.
+fn taken(destination: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", destination.display()),
+    )
+}
+
+/// Copies the [`source`] into a new file at the [`destination`] along with its permissions without blocking the runtime.
+/// With [`verify`] returns checksums of the source and the destination.
+async fn copy(
+    source: &Path,
+    destination: &Path,
+    verify: bool,
+) -> io::Result<Option<(Checksum, Checksum)>> {
+    let (source, destination) = (source.to_path_buf(), destination.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        let mut reader = File::open(&source)?;
+        let mut writer = create_new(&destination)?;
+        let checksum = if verify {
+            Some(Checksum::copy(&mut reader, &mut writer)?)
+        } else {
+            io::copy(&mut reader, &mut writer)?;
+            None
+        };
+        writer.set_permissions(reader.metadata()?.permissions())?;
+
+        match checksum {
+            // Reading the copy out of the page cache would only return what has just been written
+            Some(checksum) if evict_from_cache(&writer) => {
+                Ok(Some((checksum, Checksum::of_file(&destination)?)))
+            }
+            Some(checksum) => Ok(Some((checksum, checksum))),
+            None => Ok(None),
+        }
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Writes the [`file`] to its storage and drops its content from the page cache, so that it's read from the storage
+/// the next time. Returns whether it has been dropped.
+#[cfg(target_os = "linux")]
+fn evict_from_cache(file: &File) -> bool {
+    use std::os::fd::AsRawFd;
+
+    // Only the pages, which have already been written, are dropped.
+    // SAFETY: the descriptor stays open, while the file is borrowed
+    file.sync_data().is_ok()
+        && unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } == 0
+}
+
+/// The page cache can't be bypassed portably, so the copy isn't read once again.
+#[cfg(not(target_os = "linux"))]
+fn evict_from_cache(_file: &File) -> bool {
+    false
+}
+
+/// Renames the [`source`] to the [`destination`], unless the destination is taken, as renaming would replace it.
+async fn rename(source: &Path, destination: &Path) -> io::Result<()> {
+    if tokio::fs::symlink_metadata(destination).await.is_ok() {
+        return Err(taken(destination));
+    }
+    tokio::fs::rename(source, destination).await
+}
+
+/// Computes the checksum of the file at [`path`] without blocking the runtime.
+async fn checksum_of(path: &Path) -> io::Result<Checksum> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || Checksum::of_file(&path))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Creates a symbolic link to the absolute path of the [`source`], so that it doesn't depend on the current directory.
+async fn symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    let source = tokio::fs::canonicalize(source).await?;
+
+    #[cfg(unix)]
+    let linked = tokio::fs::symlink(source, destination).await;
+    #[cfg(windows)]
+    let linked = tokio::fs::symlink_file(source, destination).await;
+    #[cfg(not(any(unix, windows)))]
+    let linked = Err(io::Error::from(io::ErrorKind::Unsupported));
+
+    linked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "test_small_dir/1-010.dcm";
+
+    fn transfer_into(dir: &Path, mode: TransferMode, source: &Path) -> io::Result<Transferred> {
+        let destination = dir.join(format!("{}.dcm", mode.done()));
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(transfer(mode, source, &destination, true, true))
+    }
+
+    #[test]
+    fn test_transfer_modes() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let content = std::fs::read(SOURCE)?;
+        let checksum = Checksum::of_bytes(&content);
+
+        for mode in [
+            TransferMode::Copy,
+            TransferMode::Hardlink,
+            TransferMode::Symlink,
+            TransferMode::Reflink,
+        ] {
+            let transferred = transfer_into(dir.path(), mode, Path::new(SOURCE))?;
+            assert_eq!(
+                transferred.checksums,
+                Some((checksum, checksum)),
+                "{mode:?}"
+            );
+            let destination = dir.path().join(format!("{}.dcm", mode.done()));
+            assert_eq!(std::fs::read(&destination)?, content, "{mode:?}");
+        }
+        assert!(std::fs::symlink_metadata(dir.path().join("symlinked.dcm"))?
+            .file_type()
+            .is_symlink());
+
+        let source = dir.path().join("source.dcm");
+        std::fs::copy(SOURCE, &source)?;
+        assert!(transfer_into(dir.path(), TransferMode::Move, &source)?.in_mode);
+        assert!(!source.exists());
+        assert_eq!(std::fs::read(dir.path().join("moved.dcm"))?, content);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transfer_falls_back_to_copying() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("source.dcm");
+        let destination = dir.path().join("destination.dcm");
+        std::fs::copy(SOURCE, &source)?;
+
+        // Files on different file systems can't be moved
+        let moved = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(transfer(
+                TransferMode::Move,
+                &source,
+                &destination,
+                false,
+                false,
+            ))?;
+        assert!(!moved.in_mode);
+        assert!(!source.exists());
+        assert_eq!(std::fs::read(&destination)?, std::fs::read(SOURCE)?);
+        assert!(same_file_system(dir.path(), &destination));
+        assert!(same_file_system(&dir.path().join("missing"), &destination));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transfer_never_replaces_files() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("source.dcm");
+        std::fs::copy(SOURCE, &source)?;
+
+        for mode in [
+            TransferMode::Copy,
+            TransferMode::Move,
+            TransferMode::Hardlink,
+            TransferMode::Symlink,
+            TransferMode::Reflink,
+        ] {
+            let destination = dir.path().join(format!("{}.dcm", mode.done()));
+            std::fs::write(&destination, "taken")?;
+
+            let err = transfer_into(dir.path(), mode, &source).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AlreadyExists, "{mode:?}");
+            assert_eq!(std::fs::read(&destination)?, b"taken", "{mode:?}");
+        }
+        assert!(source.exists());
+
+        let profile = Arc::new(Profile::new(Default::default()));
+        let anonymized = dir.path().join("copied.dcm");
+        let err = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(anonymize(profile, &source, &anonymized))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&anonymized)?, b"taken");
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 use std::{
     borrow::Cow,
     ffi::OsString,
@@ -11,6 +12,16 @@ pub struct Person {
     pub id: OsString,
 }
 
+impl Serialize for Person {
+    /// Serializes the person as a struct with `name` and `id`, which aren't valid Unicode are converted lossily.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Person", 2)?;
+        state.serialize_field("name", &self.name.to_string_lossy())?;
+        state.serialize_field("id", &self.id.to_string_lossy())?;
+        state.end()
+    }
+}
+
 /// Relies on inner paths being sorted in the topological order as its invariant.
 /// ## Usage
 /// **Example**
@@ -32,7 +43,7 @@ pub struct Person {
 /// // line below doesn't compile
 /// let sorted_paths = SortedPaths(vec![PathBuf::from("drive/db/a.txt"), PathBuf::from("drive/da/b.txt")]);
 /// ````   
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct SortedPaths(Vec<PathBuf>);
 
 impl SortedPaths {
@@ -43,11 +54,36 @@ impl SortedPaths {
         Self(paths)
     }
 
+    /// Inserts [`path`] at its place in the topological order.
+    pub fn insert(&mut self, path: PathBuf) {
+        let index = self.0.partition_point(|existing| existing <= &path);
+        self.0.insert(index, path);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PathBuf> {
+        self.0.iter()
+    }
+
     pub fn into_inner(self) -> Vec<PathBuf> {
         self.0
     }
 }
 
+impl Serialize for SortedPaths {
+    /// Serializes paths as a sequence of strings, which aren't valid Unicode are converted lossily.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|path| path.to_string_lossy()))
+    }
+}
+
 // TODO: Currently this prints the directory sub-tree like this:
 // --------------------
 //  root
@@ -132,6 +168,7 @@ where
 }
 
 pub mod errors {
+    use indicatif::HumanBytes;
     use std::path::PathBuf;
 
     pub type CliResult<T> = Result<T, CliError>;
@@ -150,5 +187,45 @@ pub mod errors {
         GeneralError,
         #[error("Couldn't create {0} directory")]
         CreatingDirectoryError(PathBuf),
+        #[error("Couldn't remove {0}")]
+        RemovingFileError(PathBuf),
+        #[error("Couldn't use the catalog index in {0}")]
+        IndexError(PathBuf),
+        #[error("Couldn't read person IDs from {0}")]
+        IdsFileError(PathBuf),
+        #[error("Directory {0} isn't writable")]
+        NotWritable(PathBuf),
+        #[error(
+            "Directory {0} doesn't have enough free space: {} are needed, but only {} are available",
+            HumanBytes(*.1),
+            HumanBytes(*.2)
+        )]
+        NotEnoughSpace(PathBuf, u64, u64),
+        #[error("Files collide with each other in {0}, so none of them have been restructured")]
+        FilesCollide(PathBuf),
+        #[error("Couldn't read the plan from {0}")]
+        PlanError(PathBuf),
+        #[error("Couldn't use the journal in {0}")]
+        JournalError(PathBuf),
+        #[error("Couldn't use checksums in {0}")]
+        ChecksumsError(PathBuf),
+        #[error("Directory {0} doesn't contain checksums")]
+        NoChecksums(PathBuf),
+        #[error("Some files in {0} don't match their checksums")]
+        VerificationFailed(PathBuf),
+        #[error("Couldn't write the report into {0}")]
+        ReportError(PathBuf),
+        #[error("{0} files have failed")]
+        FilesFailed(usize),
+        #[error("{0} files have been skipped")]
+        FilesSkipped(usize),
+        #[error("Couldn't open the log file {0}")]
+        LogFileError(PathBuf),
+        #[error("Couldn't write the patients' mapping into {0}")]
+        PatientsError(PathBuf),
+        #[error("Couldn't use the vault {0}")]
+        VaultError(PathBuf),
+        #[error("{0} is accessible by others with permissions {1:o}, it has to be restricted to 600 first")]
+        VaultPermissions(PathBuf, u32),
     }
 }
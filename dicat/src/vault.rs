@@ -0,0 +1,221 @@
+use dicom::dictionary_std::tags;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use crate::{
+    errors::{CliError, CliResult},
+    header::Attributes,
+};
+
+/// Permissions, which the vault and its audit log are created with and which they mustn't exceed.
+pub const VAULT_PERMISSIONS: u32 = 0o600;
+
+/// Tags, which pseudonyms or new UIDs of are recorded in the vault.
+pub const MAPPED_TAGS: [dicom::core::Tag; 4] = [
+    tags::PATIENT_ID,
+    tags::STUDY_INSTANCE_UID,
+    tags::SERIES_INSTANCE_UID,
+    tags::SOP_INSTANCE_UID,
+];
+
+/// Key of the pseudonymization along with the pseudonyms and the new UIDs, which it has produced, mapped to the
+/// original values. The same key makes the same pseudonyms across runs, so the vault has to be kept secret
+/// and is only readable by its owner.
+/// ## Usage
+/// **Example**
+/// ```
+/// use dicat::vault::Vault;
+/// use dicom::dictionary_std::tags;
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("vault.json");
+///
+/// let mut vault = Vault::open_or_create(&path).unwrap();
+/// let original = [(tags::PATIENT_ID, "98.12.21".to_string())].into();
+/// let pseudonymized = [(tags::PATIENT_ID, "PSEUDO-0A1B2C3D4E5F6789".to_string())].into();
+/// vault.record(&original, &pseudonymized);
+/// vault.save(&path).unwrap();
+///
+/// let vault = Vault::open(&path).unwrap();
+/// assert_eq!(vault.reidentify("PSEUDO-0A1B2C3D4E5F6789"), Some("98.12.21"));
+/// ```
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vault {
+    key: [u8; 32],
+    /// Original values by their pseudonyms and new UIDs
+    #[serde(default)]
+    mapping: BTreeMap<String, String>,
+}
+
+impl Vault {
+    /// Opens the vault at the [`path`] or creates a new one with a random key, when it doesn't exist.
+    /// A new vault isn't written until it's [`saved`](Vault::save).
+    pub fn open_or_create(path: &Path) -> CliResult<Self> {
+        if path.exists() {
+            return Self::open(path);
+        }
+
+        let mut key = [0; 32];
+        getrandom::fill(&mut key).expect("Failed getting random bytes");
+        Ok(Self {
+            key,
+            mapping: BTreeMap::new(),
+        })
+    }
+
+    /// Opens the existing vault at the [`path`], which mustn't be accessible by anyone, but its owner.
+    pub fn open(path: &Path) -> CliResult<Self> {
+        check_permissions(path)?;
+        let file = File::open(path).map_err(vault_error(path))?;
+        serde_json::from_reader(BufReader::new(file)).map_err(vault_error(path))
+    }
+
+    /// Key, which the pseudonyms and the new UIDs are derived with.
+    pub fn key(&self) -> [u8; 32] {
+        self.key
+    }
+
+    /// Records pseudonyms and new UIDs of the [`pseudonymized`] attributes along with their [`original`] values.
+    pub fn record(&mut self, original: &Attributes, pseudonymized: &Attributes) {
+        for tag in MAPPED_TAGS {
+            if let (Some(original), Some(pseudonym)) = (original.get(&tag), pseudonymized.get(&tag))
+            {
+                if !pseudonym.is_empty() && pseudonym != original {
+                    self.mapping.insert(pseudonym.clone(), original.clone());
+                }
+            }
+        }
+    }
+
+    /// Original value of the [`pseudonym`], which is either a pseudonymized `PatientID` or a new UID.
+    pub fn reidentify(&self, pseudonym: &str) -> Option<&str> {
+        self.mapping.get(pseudonym.trim()).map(String::as_str)
+    }
+
+    /// All pseudonyms and new UIDs along with their original values.
+    pub fn mapping(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.mapping
+            .iter()
+            .map(|(pseudonym, original)| (pseudonym.as_str(), original.as_str()))
+    }
+
+    /// Writes the vault to the [`path`] with [`VAULT_PERMISSIONS`]. The previous one is replaced only after
+    /// the new one has been completely written, so an interruption can't lose the key.
+    pub fn save(&self, path: &Path) -> CliResult<()> {
+        let temporary = path.with_extension("tmp");
+        let mut file = create_private(&temporary, false).map_err(vault_error(path))?;
+        // Serializing plain data into JSON can't fail
+        serde_json::to_writer_pretty(&mut file, self).unwrap();
+        file.sync_all().map_err(vault_error(path))?;
+        std::fs::rename(&temporary, path).map_err(vault_error(path))
+    }
+
+    /// Appends a record of the re-identification of the [`pseudonyms`] along with its [`reason`] to the audit log
+    /// next to the vault at [`path`].
+    pub fn audit(path: &Path, reason: &str, pseudonyms: &[String]) -> CliResult<()> {
+        let log_path = audit_path(path);
+        if log_path.exists() {
+            check_permissions(&log_path)?;
+        }
+        let mut file = create_private(&log_path, true).map_err(vault_error(&log_path))?;
+
+        let record = serde_json::json!({
+            "time": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            "reason": reason,
+            "pseudonyms": pseudonyms,
+        });
+        writeln!(file, "{record}").map_err(vault_error(&log_path))
+    }
+}
+
+/// Audit log of the vault at the [`path`], which records every re-identification.
+pub fn audit_path(path: &Path) -> std::path::PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".audit");
+    path.with_file_name(name)
+}
+
+/// Opens the file at the [`path`] for writing, creating it with [`VAULT_PERMISSIONS`] when it doesn't exist.
+pub(crate) fn create_private(path: &Path, append: bool) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    if append {
+        options.append(true);
+    } else {
+        options.write(true).truncate(true);
+    }
+    options.create(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, VAULT_PERMISSIONS);
+
+    options.open(path)
+}
+
+/// Fails, when the file at the [`path`] is accessible by the group or the others.
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> CliResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = std::fs::metadata(path)
+        .map_err(vault_error(path))?
+        .permissions()
+        .mode();
+    if mode & 0o777 & !VAULT_PERMISSIONS != 0 {
+        return Err(CliError::VaultPermissions(path.to_path_buf(), mode & 0o777));
+    }
+    Ok(())
+}
+
+/// Permissions of the owner can't be told apart from the others' ones on this platform.
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> CliResult<()> {
+    Ok(())
+}
+
+/// Maps any error of the underlying storage into [`CliError::VaultError`].
+fn vault_error<E>(path: &Path) -> impl Fn(E) -> CliError + '_ {
+    move |_| CliError::VaultError(path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vault_is_private() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+
+        let vault = Vault::open_or_create(&path).unwrap();
+        assert!(!path.exists());
+        vault.save(&path).unwrap();
+        assert_eq!(Vault::open_or_create(&path).unwrap(), vault);
+
+        Vault::audit(&path, "IRB 2024-17", &["PSEUDO-1".to_string()]).unwrap();
+        Vault::audit(&path, "IRB 2024-18", &[]).unwrap();
+        let audit = std::fs::read_to_string(audit_path(&path)).unwrap();
+        assert_eq!(audit.lines().count(), 2);
+        assert!(audit.contains("IRB 2024-17"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            for path in [&path, &audit_path(&path)] {
+                let mode = std::fs::metadata(path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, VAULT_PERMISSIONS);
+            }
+
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(matches!(
+                Vault::open(&path),
+                Err(CliError::VaultPermissions(_, 0o644))
+            ));
+        }
+    }
+}